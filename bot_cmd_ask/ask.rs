@@ -1,4 +1,7 @@
-use crate::{DECLINE_BUTTON_ID, JOIN_ADVANCED_BUTTON_ID, JOIN_BUTTON_ID, LEAVE_BUTTON_ID, TOGGLE_GAME_ROLE_BUTTON_ID};
+use crate::{
+    DECLINE_BUTTON_ID, JOIN_ADVANCED_BUTTON_ID, JOIN_BUTTON_ID, LEAVE_BUTTON_ID, SHUFFLE_TEAMS_BUTTON_ID,
+    TOGGLE_GAME_ROLE_BUTTON_ID,
+};
 use bot_core::time::discord_timestamp;
 use chrono::{DateTime, TimeDelta, Utc};
 use itertools::{Either, Itertools};
@@ -23,6 +26,8 @@ pub(crate) struct Ask {
     #[serde(with = "chrono::serde::ts_seconds")]
     pub start_time: DateTime<Utc>,
     pub pinged: bool,
+    #[serde(default)]
+    pub host: Option<UserId>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -111,7 +116,7 @@ impl Ask {
             .map(|(id, _)| *id)
     }
 
-    fn joined_players(&self) -> (Vec<UserId>, Vec<(DateTime<Utc>, UserId)>) {
+    pub(crate) fn joined_players(&self) -> (Vec<UserId>, Vec<(DateTime<Utc>, UserId)>) {
         let now = Utc::now();
         self.players
             .iter()
@@ -139,7 +144,12 @@ impl Ask {
         delta < TimeDelta::seconds(3)
     }
 
-    pub(crate) fn action_row(&self) -> CreateActionRow {
+    pub(crate) fn is_host(&self, user_id: UserId) -> bool {
+        // asks from before hosts were tracked can be managed by anyone
+        self.host.is_none_or(|host| host == user_id)
+    }
+
+    pub(crate) fn components(&self) -> Vec<CreateActionRow> {
         let mut buttons = vec![
             CreateButton::new(JOIN_BUTTON_ID).style(ButtonStyle::Success).label("Join"),
             CreateButton::new(JOIN_ADVANCED_BUTTON_ID).style(ButtonStyle::Success).label("Later…"),
@@ -149,7 +159,10 @@ impl Ask {
         if let AskRoleId::KnownGame(_) = self.role_id {
             buttons.push(CreateButton::new(TOGGLE_GAME_ROLE_BUTTON_ID).style(ButtonStyle::Secondary).emoji('🔔'));
         }
-        CreateActionRow::Buttons(buttons)
+        let host_buttons = vec![
+            CreateButton::new(SHUFFLE_TEAMS_BUTTON_ID).style(ButtonStyle::Secondary).label("Shuffle teams").emoji('🎲'),
+        ];
+        vec![CreateActionRow::Buttons(buttons), CreateActionRow::Buttons(host_buttons)]
     }
 
     pub(crate) fn ping(&mut self, msg_id: MessageId) -> Option<CreateMessage> {
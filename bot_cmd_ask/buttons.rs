@@ -1,8 +1,10 @@
 use crate::ask::{AskPlayer, AskPlayerState, AskRoleId};
+use crate::game_role_menu::{game_role_menu, parse_menu_param};
 use crate::schedule_updates::spawn_delayed_update;
+use crate::teams::{record_teams, shuffle_teams};
 use crate::{
-    ConfigT, Game, JOIN_ADVANCED_SUBMIT_BUTTON_ID, LEAVE_SERVER_BUTTON_ID, SHOW_GAME_ROLES_SELECT_ID,
-    SUBMIT_GAME_ROLES_SELECT_ID, StateT, worker_ask_update, worker_game_roles,
+    ConfigT, JOIN_ADVANCED_SUBMIT_BUTTON_ID, LEAVE_SERVER_BUTTON_ID, SHOW_GAME_ROLES_SELECT_ID,
+    SHUFFLE_TEAMS_SUBMIT_BUTTON_ID, StateT, worker_ask_update, worker_game_roles,
 };
 use bot_core::ext::create_reply::CreateReplyExt;
 use bot_core::ext::option::OptionExt;
@@ -16,10 +18,9 @@ use poise::CreateReply;
 use poise::serenity_prelude::prelude::Mentionable;
 use poise::serenity_prelude::{
     ButtonStyle, Colour, ComponentInteraction, ComponentInteractionDataKind, CreateActionRow, CreateButton,
-    CreateEmbed, CreateInputText, CreateQuickModal, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
-    InputTextStyle, MessageId, RoleId,
+    CreateEmbed, CreateInputText, CreateMessage, CreateQuickModal, InputTextStyle, InteractionId, MessageId, RoleId,
 };
-use std::collections::{BTreeMap, HashSet, btree_map};
+use std::collections::{HashSet, btree_map};
 use std::time::Duration;
 
 pub enum AskEvent {
@@ -166,6 +167,72 @@ pub async fn btn_leave_server(ctx: EvtContext<'_, impl With<ConfigT>>, component
     Ok(())
 }
 
+pub async fn btn_shuffle_teams(
+    ctx: EvtContext<'_, impl With<ConfigT> + State<StateT>>,
+    interaction: &ComponentInteraction,
+) -> Result<()> {
+    let ask_id = interaction.message.id;
+    let player_count = ctx
+        .user_data
+        .with(|cfg| {
+            let ask = cfg.asks.get(&ask_id).ok_or_eyre("Unknown /ask")?;
+            ensure!(ask.is_host(interaction.user.id), "Only the host can shuffle teams");
+            Ok(ask.joined_players().0.len())
+        })
+        .await?;
+    ensure!(player_count >= 2, "Not enough players to shuffle teams");
+
+    let buttons = (2..=player_count.min(5))
+        .map(|n| {
+            CreateButton::new(format!("{SHUFFLE_TEAMS_SUBMIT_BUTTON_ID}:{ask_id}:{n}"))
+                .label(format!("{n} teams"))
+                .style(ButtonStyle::Primary)
+        })
+        .collect_vec();
+    CreateReply::new()
+        .ephemeral(true)
+        .components(vec![CreateActionRow::Buttons(buttons)])
+        .respond_to_component(ctx.serenity_context, interaction)
+        .await?;
+    Ok(())
+}
+
+pub async fn btn_shuffle_teams_submit(
+    ctx: EvtContext<'_, impl With<ConfigT> + State<StateT>>,
+    interaction: &ComponentInteraction,
+    param: &str,
+) -> Result<()> {
+    let (ask_id_param, team_count_param) = param.split_once(':').ok_or_eyre("Invalid parameter format")?;
+    let ask_id = ask_id_param.parse::<MessageId>().wrap_err("Invalid ask ID")?;
+    let team_count = team_count_param.parse::<usize>().wrap_err("Invalid team count")?;
+
+    interaction.defer(ctx.serenity_context).await?;
+
+    let (channel_id, teams) = ctx
+        .user_data
+        .with_mut(|cfg| {
+            let ask = cfg.asks.get(&ask_id).ok_or_eyre("Unknown /ask")?;
+            ensure!(ask.is_host(interaction.user.id), "Only the host can shuffle teams");
+            let players = ask.joined_players().0;
+            ensure!(players.len() >= team_count, "Not enough players for {team_count} teams");
+            let channel_id = ask.channel_id;
+            let teams = shuffle_teams(&players, team_count, &cfg.teammates);
+            record_teams(&mut cfg.teammates, &teams);
+            Ok((channel_id, teams))
+        })
+        .await?;
+
+    let embed =
+        teams.iter().enumerate().fold(CreateEmbed::new().title("Teams").colour(Colour::BLUE), |embed, (i, team)| {
+            embed.field(format!("Team {}", i + 1), team.iter().map(|u| u.mention()).join(" "), true)
+        });
+    channel_id
+        .send_message(ctx.serenity_context, CreateMessage::new().reference_message((channel_id, ask_id)).embed(embed))
+        .await?;
+
+    Ok(())
+}
+
 pub async fn btn_toggle_game_role(
     ctx: EvtContext<'_, impl With<ConfigT> + State<StateT>>,
     component: &ComponentInteraction,
@@ -241,56 +308,35 @@ pub async fn btn_show_parent_role_buttons(
 }
 
 pub async fn btn_show_game_role_selection(
-    ctx: EvtContext<'_, impl With<ConfigT>>,
+    ctx: EvtContext<'_, impl With<ConfigT> + State<StateT>>,
     interaction: &ComponentInteraction,
     param: &str,
 ) -> Result<()> {
-    let parent_role = param.parse::<RoleId>()?;
-    let user_id = interaction.user.id;
+    let (filter, page) = parse_menu_param(param)?;
     let guild_id = interaction.guild_id.some()?;
+    let cache = &ctx.serenity_context.cache;
 
-    let game_roles: BTreeMap<RoleId, Game> = ctx
-        .user_data
-        .with_ok(|cfg| {
-            cfg.games.iter().filter(|(_, game)| game.parent_role == parent_role).map(|(k, v)| (*k, v.clone())).collect()
-        })
+    game_role_menu(cache, ctx.user_data, guild_id, interaction.user.id, interaction.id, filter, page)
+        .await?
+        .ephemeral(true)
+        .respond_to_component(ctx.serenity_context, interaction)
         .await?;
-    ensure!(!game_roles.is_empty(), "There are no game roles for {}", parent_role.mention());
-    ensure!(game_roles.len() <= 25, "Discord can't display more than 25 options");
 
-    let options = {
-        let guild = ctx.serenity_context.cache.guild(guild_id).some()?;
-        game_roles
-            .into_iter()
-            .filter_map(|(role_id, game)| Some((guild.roles.get(&role_id)?, game)))
-            .sorted_by_key(|(role, _)| &role.name)
-            .map(|(role, game)| {
-                let mut option = CreateSelectMenuOption::new(role.name.clone(), role.id.to_string())
-                    .default_selection(!game.opted_out_users.contains(&user_id));
-                if let Some(mut description) = game.defaults.description {
-                    if description.len() > 100 {
-                        description.truncate(97);
-                        description.push('…');
-                    }
-                    option = option.description(description);
-                }
-                option
-            })
-            .collect_vec()
-    };
+    Ok(())
+}
 
-    let max_values = options.len() as u8;
-    CreateReply::new()
-        .components(vec![CreateActionRow::SelectMenu(
-            CreateSelectMenu::new(
-                format!("{SUBMIT_GAME_ROLES_SELECT_ID}:{parent_role}"),
-                CreateSelectMenuKind::String { options },
-            )
-            .min_values(0)
-            .max_values(max_values),
-        )])
-        .ephemeral(true)
-        .respond_to_component(ctx.serenity_context, interaction)
+pub async fn btn_game_roles_page(
+    ctx: EvtContext<'_, impl With<ConfigT> + State<StateT>>,
+    interaction: &ComponentInteraction,
+    param: &str,
+) -> Result<()> {
+    let (filter, page) = parse_menu_param(param)?;
+    let guild_id = interaction.guild_id.some()?;
+    let cache = &ctx.serenity_context.cache;
+
+    game_role_menu(cache, ctx.user_data, guild_id, interaction.user.id, interaction.id, filter, page)
+        .await?
+        .update_to_component(ctx.serenity_context, interaction)
         .await?;
 
     Ok(())
@@ -301,33 +347,33 @@ pub async fn select_roles(
     interaction: &ComponentInteraction,
     param: &str,
 ) -> Result<()> {
-    let parent_role = param.parse::<RoleId>()?;
+    let offered = ctx.user_data.state().offered_games.get(param.parse::<InteractionId>()?)?;
     let user_id = interaction.user.id;
+    let guild_id = interaction.guild_id.some()?;
     let ComponentInteractionDataKind::StringSelect { values } = interaction.data.kind.clone() else {
         bail!("Unexpected interaction kind: {:?}", interaction.data.kind);
     };
     let selected: HashSet<RoleId> = values.into_iter().filter_map(|s| s.parse().ok()).collect();
 
-    interaction.defer(ctx.serenity_context).await?;
-
     ctx.user_data
         .with_mut_ok(|cfg| {
-            for (role_id, game) in &mut cfg.games {
-                if game.parent_role == parent_role {
-                    if selected.contains(role_id) {
-                        game.opted_out_users.remove(&user_id);
-                    } else {
-                        // This incorrectly also removes game roles
-                        // that were created after we sent the dropdown.
-                        // To fix that we'd have to parse the original dropdown
-                        // or store the unselected options somehow which would be annoying.
-                        game.opted_out_users.insert(user_id);
-                    }
+            for role_id in &offered.roles {
+                let Some(game) = cfg.games.get_mut(role_id) else { continue };
+                if selected.contains(role_id) {
+                    game.opted_out_users.remove(&user_id);
+                } else {
+                    game.opted_out_users.insert(user_id);
                 }
             }
         })
         .await?;
 
+    let cache = &ctx.serenity_context.cache;
+    game_role_menu(cache, ctx.user_data, guild_id, user_id, interaction.id, offered.filter, offered.page)
+        .await?
+        .update_to_component(ctx.serenity_context, interaction)
+        .await?;
+
     ctx.user_data.state().game_role_sender.get().some()?.send(worker_game_roles::Command::Update).await?;
 
     Ok(())
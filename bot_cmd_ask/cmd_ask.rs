@@ -2,9 +2,11 @@ use crate::ask::{Ask, AskPlayer, AskPlayerState, AskRoleId};
 use crate::schedule_updates::schedule_ask_updates;
 use crate::{ConfigT, StateT};
 use bot_core::ext::option::OptionExt as _;
-use bot_core::{CmdContext, State, With, naive_time_to_next_datetime};
-use chrono::{NaiveTime, Utc};
-use eyre::Result;
+use bot_core::time::human::parse_datetime;
+use bot_core::time::local_timezone;
+use bot_core::{CmdContext, State, With};
+use chrono::Utc;
+use eyre::{OptionExt as _, Result};
 use poise::serenity_prelude::{CreateAllowedMentions, Guild, GuildChannel, RoleId};
 use url::Url;
 
@@ -15,15 +17,19 @@ pub async fn ask<D: With<ConfigT> + State<StateT>>(
     #[description = "Game title"] title: String,
     #[description = "Minimum number of players"] min_players: Option<u32>,
     #[description = "Maximum number of players"] max_players: Option<u32>,
-    #[string]
-    #[autocomplete = bot_core::autocomplete::time]
-    #[description = "Start time"]
-    start_time: Option<NaiveTime>,
+    #[autocomplete = bot_core::autocomplete::datetime]
+    #[description = "Start time, e.g. 20:30, in 2h or fri 19:00"]
+    start_time: Option<String>,
     #[string]
     #[description = "Link to the game"]
     url: Option<Url>,
     #[description = "Game description"] description: Option<String>,
 ) -> Result<()> {
+    let now = Utc::now();
+    let start_time = start_time
+        .map(|input| parse_datetime(&input, &local_timezone(), now).ok_or_eyre("Invalid start time"))
+        .transpose()?;
+
     let (game_with_name, expiration) = ctx
         .data()
         .with(|cfg| {
@@ -52,7 +58,6 @@ pub async fn ask<D: With<ConfigT> + State<StateT>>(
         }
     };
 
-    let now = Utc::now();
     let author_player = AskPlayer { state: AskPlayerState::Joined, entered_at: now };
     let defaults = game.map(|g| g.defaults);
     let ask = Ask {
@@ -65,8 +70,9 @@ pub async fn ask<D: With<ConfigT> + State<StateT>>(
         thumbnail_url: defaults.as_ref().and_then(|d| d.thumbnail_url.clone()),
         channel_id: ctx.channel_id(),
         role_id,
-        start_time: start_time.and_then(naive_time_to_next_datetime).map_or(now, |dt| dt.to_utc()),
+        start_time: start_time.map_or(now, |dt| dt.to_utc()),
         pinged: false,
+        host: Some(ctx.author().id),
     };
 
     let msg_id = {
@@ -74,7 +80,7 @@ pub async fn ask<D: With<ConfigT> + State<StateT>>(
             .content(format!("{} {}", ask.title, ask.content()))
             .embed(ask.embed())
             .allowed_mentions(CreateAllowedMentions::new().roles(ask.role_id.into_option()))
-            .components(vec![ask.action_row()]);
+            .components(ask.components());
         let reply_handle = ctx.send(reply).await?;
         reply_handle.message().await?.id
     };
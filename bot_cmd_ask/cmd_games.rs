@@ -0,0 +1,17 @@
+use crate::game_role_menu::{GameFilter, game_role_menu};
+use crate::{ConfigT, StateT};
+use bot_core::ext::option::OptionExt as _;
+use bot_core::{CmdContext, State, With};
+use eyre::Result;
+use poise::serenity_prelude::InteractionId;
+
+/// Subscribe to or unsubscribe from game pings
+#[poise::command(slash_command, guild_only)]
+pub async fn games<D: With<ConfigT> + State<StateT>>(ctx: CmdContext<'_, D>) -> Result<()> {
+    let guild_id = ctx.guild_id().some()?;
+    let key = InteractionId::new(ctx.id());
+    let cache = &ctx.serenity_context().cache;
+    let reply = game_role_menu(cache, ctx.data(), guild_id, ctx.author().id, key, GameFilter::All, 0).await?;
+    ctx.send(reply.ephemeral(true)).await?;
+    Ok(())
+}
@@ -0,0 +1,337 @@
+use crate::{ConfigT, Game, GameDefaults, StateT, worker_game_roles};
+use bot_core::ext::option::OptionExt as _;
+use bot_core::serde::LiteralRegex;
+use bot_core::{CmdContext, State, With};
+use eyre::{OptionExt as _, Result, WrapErr as _, ensure, eyre};
+use fancy_regex::Regex;
+use itertools::Itertools as _;
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    Attachment, ButtonStyle, Colour, CreateActionRow, CreateButton, CreateEmbed, EditRole, Guild, Mentionable as _,
+    Permissions, RoleId,
+};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+use url::Url;
+
+/// Bulk import games from a YAML/CSV catalog or by adopting existing roles
+#[poise::command(
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn import_ask_games<D: With<ConfigT> + State<StateT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "YAML or CSV list of games (fields of /configure_ask_game)"] catalog: Option<Attachment>,
+    #[description = "Adopt existing roles whose name matches this regex"] role_pattern: Option<String>,
+    #[description = "Parent role for adopted roles and catalog entries without one"] parent_role: Option<RoleId>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().some()?;
+
+    ctx.defer().await?;
+
+    let catalog = match catalog {
+        Some(attachment) => {
+            let bytes = attachment.download().await?;
+            parse_catalog(&attachment.filename, &String::from_utf8_lossy(&bytes))?
+        }
+        None => vec![],
+    };
+    let role_pattern = role_pattern.map(|p| Regex::new(&format!("(?i){p}"))).transpose().wrap_err("Invalid regex")?;
+
+    let games = ctx.data().with_ok(|cfg| cfg.games.clone()).await?;
+    let planned = {
+        let guild = ctx.guild().some()?;
+        plan_import(&guild, &games, catalog, role_pattern.as_ref(), parent_role)?
+    };
+
+    let embed = summary_embed(&planned);
+
+    let confirm_id = "~ask.import_confirm";
+    let cancel_id = "~ask.import_cancel";
+
+    let handle = ctx
+        .send(CreateReply::new().embed(embed.clone()).components(vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(confirm_id).label("Import").style(ButtonStyle::Success),
+            CreateButton::new(cancel_id).label("Cancel").style(ButtonStyle::Danger),
+        ])]))
+        .await?;
+    let message = handle.message().await?;
+
+    let first_interaction = async {
+        while let Some(interaction) =
+            message.await_component_interaction(ctx.serenity_context()).timeout(Duration::from_secs(120)).await
+        {
+            if interaction.user.id == ctx.author().id {
+                return Some(interaction);
+            }
+        }
+        None
+    }
+    .await;
+
+    // deactivate confirmation message in all cases
+    handle.edit(ctx, CreateReply::new().embed(embed.clone().colour(Colour::DARKER_GREY)).components(vec![])).await?;
+
+    let Some(interaction) = first_interaction.filter(|i| i.data.custom_id == confirm_id) else {
+        return Ok(());
+    };
+    interaction.defer(ctx).await?;
+
+    // create the roles one by one and keep whatever succeeded if Discord refuses one of them
+    let mut imported = vec![];
+    let mut error = None;
+    for game in planned {
+        let role_id = match game.role {
+            RoleSource::Adopt(id) | RoleSource::Update(id) => id,
+            RoleSource::Create => {
+                let role_builder = {
+                    let guild = ctx.guild().some()?;
+                    let parent_role = guild.roles.get(&game.parent_role).ok_or_eyre("Parent role not found")?;
+                    EditRole::new()
+                        .name(game.name.clone())
+                        .permissions(Permissions::empty())
+                        .colour(parent_role.colour)
+                        .mentionable(parent_role.mentionable)
+                        .audit_log_reason("Imported game role from parent role")
+                };
+                match guild_id.create_role(ctx, role_builder).await {
+                    Ok(role) => role.id,
+                    Err(e) => {
+                        error = Some(eyre!(e).wrap_err(format!("Failed to create role {}", game.name)));
+                        break;
+                    }
+                }
+            }
+        };
+        imported.push((role_id, game));
+    }
+
+    let count = imported.len();
+    ctx.data()
+        .with_mut_ok(|cfg| {
+            for (role_id, game) in imported {
+                match cfg.games.entry(role_id) {
+                    Entry::Occupied(mut entry) => {
+                        let existing = entry.get_mut();
+                        existing.parent_role = game.parent_role;
+                        existing.title_pattern = game.title_pattern;
+                        existing.defaults = game.defaults;
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(Game {
+                            parent_role: game.parent_role,
+                            title_pattern: game.title_pattern,
+                            defaults: game.defaults,
+                            opted_out_users: Default::default(),
+                        });
+                    }
+                }
+            }
+        })
+        .await?;
+
+    ctx.data().state().game_role_sender.get().some()?.send(worker_game_roles::Command::Update).await?;
+
+    if let Some(error) = error {
+        handle.edit(ctx, CreateReply::new().embed(embed.colour(Colour::RED)).components(vec![])).await?;
+        return Err(error.wrap_err(format!("Imported only {count} games")));
+    }
+
+    handle.edit(ctx, CreateReply::new().embed(embed.colour(Colour::DARK_GREEN)).components(vec![])).await?;
+
+    Ok(())
+}
+
+/// A game as listed in an imported catalog
+#[derive(serde::Deserialize, Debug, PartialEq)]
+struct CatalogEntry {
+    name: String,
+    /// Role name, id or mention
+    parent_role: Option<String>,
+    title_pattern: Option<String>,
+    min_players: Option<u32>,
+    max_players: Option<u32>,
+    url: Option<Url>,
+    description: Option<String>,
+    thumbnail_url: Option<String>,
+}
+
+enum RoleSource {
+    Create,
+    Adopt(RoleId),
+    Update(RoleId),
+}
+
+struct PlannedGame {
+    name: String,
+    role: RoleSource,
+    parent_role: RoleId,
+    title_pattern: LiteralRegex,
+    defaults: GameDefaults,
+}
+
+fn parse_catalog(filename: &str, content: &str) -> Result<Vec<CatalogEntry>> {
+    if filename.to_lowercase().ends_with(".csv") {
+        csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(content.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .wrap_err("Invalid CSV catalog")
+    } else {
+        serde_yaml_ng::from_str(content).wrap_err("Invalid YAML catalog")
+    }
+}
+
+fn plan_import(
+    guild: &Guild,
+    games: &BTreeMap<RoleId, Game>,
+    catalog: Vec<CatalogEntry>,
+    role_pattern: Option<&Regex>,
+    default_parent: Option<RoleId>,
+) -> Result<Vec<PlannedGame>> {
+    let mut planned = vec![];
+    let mut names = HashSet::new();
+
+    for entry in catalog {
+        let name = entry.name.trim().to_string();
+        ensure!(!name.is_empty(), "The catalog contains a game without a name");
+        ensure!(names.insert(name.clone()), "{name} is listed more than once");
+        let parent_role = match &entry.parent_role {
+            Some(parent) => find_role(guild, parent)?.ok_or_else(|| eyre!("{name}: Parent role {parent} not found"))?,
+            None => default_parent.ok_or_else(|| eyre!("{name}: No parent role given"))?,
+        };
+        let pattern = entry.title_pattern.as_deref().unwrap_or(&name);
+        let title_pattern =
+            Regex::new(&format!("(?i){pattern}")).wrap_err_with(|| format!("{name}: Invalid title pattern"))?;
+        let role = match crate::get_unique_role_by_name(guild, &name)? {
+            Some(id) if games.contains_key(&id) => RoleSource::Update(id),
+            Some(id) => RoleSource::Adopt(id),
+            None => RoleSource::Create,
+        };
+        planned.push(PlannedGame {
+            name,
+            role,
+            parent_role,
+            title_pattern: LiteralRegex(title_pattern),
+            defaults: GameDefaults {
+                min_players: entry.min_players,
+                max_players: entry.max_players,
+                url: entry.url,
+                description: entry.description,
+                thumbnail_url: entry.thumbnail_url,
+            },
+        });
+    }
+
+    if let Some(role_pattern) = role_pattern {
+        let parent_role = default_parent.ok_or_eyre("Adopting roles requires a parent role")?;
+        for role in guild.roles.values().sorted_by_key(|r| &r.name) {
+            let is_parent = role.id == parent_role || games.values().any(|g| g.parent_role == role.id);
+            let is_everyone = role.id.get() == guild.id.get();
+            if is_parent || is_everyone || role.managed || games.contains_key(&role.id) || names.contains(&role.name) {
+                continue;
+            }
+            if !role_pattern.is_match(&role.name)? {
+                continue;
+            }
+            names.insert(role.name.clone());
+            let title_pattern = Regex::new(&format!("(?i){}", fancy_regex::escape(&role.name)))?;
+            planned.push(PlannedGame {
+                name: role.name.clone(),
+                role: RoleSource::Adopt(role.id),
+                parent_role,
+                title_pattern: LiteralRegex(title_pattern),
+                defaults: Default::default(),
+            });
+        }
+    }
+
+    ensure!(!planned.is_empty(), "Nothing to import");
+    Ok(planned)
+}
+
+/// Find a role by mention, id or (unique) name
+fn find_role(guild: &Guild, s: &str) -> Result<Option<RoleId>> {
+    let s = s.trim();
+    let id = s.strip_prefix("<@&").and_then(|s| s.strip_suffix('>')).unwrap_or(s);
+    if let Ok(id) = id.parse::<RoleId>()
+        && guild.roles.contains_key(&id)
+    {
+        return Ok(Some(id));
+    }
+    crate::get_unique_role_by_name(guild, s)
+}
+
+fn summary_embed(planned: &[PlannedGame]) -> CreateEmbed {
+    let lines = |f: fn(&PlannedGame) -> Option<String>| {
+        let lines = planned.iter().filter_map(|game| Some(format!("{} ({})", f(game)?, game.parent_role.mention())));
+        field_value(lines.collect_vec())
+    };
+    let created = lines(|game| matches!(game.role, RoleSource::Create).then(|| game.name.clone()));
+    let adopted = lines(|game| match game.role {
+        RoleSource::Adopt(id) => Some(id.mention().to_string()),
+        _ => None,
+    });
+    let updated = lines(|game| match game.role {
+        RoleSource::Update(id) => Some(id.mention().to_string()),
+        _ => None,
+    });
+
+    let added = planned.iter().filter(|game| !matches!(game.role, RoleSource::Update(_))).count();
+    let updated_count = planned.len() - added;
+    CreateEmbed::new()
+        .title("Import Games")
+        .description(format!("{added} games will be added and {updated_count} updated"))
+        .field("➕ New roles", created, false)
+        .field("🔗 Adopted roles", adopted, false)
+        .field("📝 Updated games", updated, false)
+        .colour(Colour::GOLD)
+}
+
+/// Join lines into an embed field value, cutting it off at Discord's limit
+fn field_value(lines: Vec<String>) -> String {
+    if lines.is_empty() {
+        return "-".to_string();
+    }
+    let mut value = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let rest = format!("… and {} more", lines.len() - i);
+        if value.len() + line.len() + rest.len() + 1 > 1024 {
+            value.push_str(&rest);
+            break;
+        }
+        value.push_str(line);
+        value.push('\n');
+    }
+    value
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csv_catalog_with_empty_fields() {
+        let csv =
+            "name,parent_role,min_players,url\nMinecraft,Survival,,https://minecraft.net\nTerraria, Survival ,2,\n";
+        let catalog = parse_catalog("games.csv", csv).unwrap();
+        assert_eq!(catalog.len(), 2);
+        assert_eq!(catalog[0].min_players, None);
+        assert_eq!(catalog[0].url.as_ref().map(Url::as_str), Some("https://minecraft.net/"));
+        assert_eq!(catalog[1].parent_role.as_deref(), Some("Survival"));
+        assert_eq!(catalog[1].min_players, Some(2));
+        assert_eq!(catalog[1].url, None);
+    }
+
+    #[test]
+    fn yaml_catalog() {
+        let yaml = "- name: Minecraft\n  parent_role: '<@&123>'\n  max_players: 8\n- name: Terraria\n";
+        let catalog = parse_catalog("games.yaml", yaml).unwrap();
+        assert_eq!(catalog[0].max_players, Some(8));
+        assert_eq!(catalog[1].parent_role, None);
+    }
+}
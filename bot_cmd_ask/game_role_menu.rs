@@ -0,0 +1,172 @@
+use crate::{ConfigT, GAME_ROLES_PAGE_BUTTON_ID, Game, SUBMIT_GAME_ROLES_SELECT_ID, StateT};
+use bot_core::ext::option::OptionExt as _;
+use bot_core::{State, With};
+use eyre::{Result, ensure};
+use itertools::Itertools as _;
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    ButtonStyle, Cache, Colour, CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, CreateSelectMenu,
+    CreateSelectMenuKind, CreateSelectMenuOption, Guild, GuildId, InteractionId, Mentionable as _, RoleId, UserId,
+};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Discord can't display more than 25 options in a select menu
+const GAMES_PER_PAGE: usize = 25;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum GameFilter {
+    All,
+    Parent(RoleId),
+}
+
+impl Display for GameFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameFilter::All => write!(f, "all"),
+            GameFilter::Parent(role_id) => write!(f, "{role_id}"),
+        }
+    }
+}
+
+impl FromStr for GameFilter {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(GameFilter::All),
+            _ => Ok(GameFilter::Parent(s.parse()?)),
+        }
+    }
+}
+
+/// Parses `<filter>:<page>`, the page is optional for buttons that were created before pagination existed
+pub(crate) fn parse_menu_param(param: &str) -> Result<(GameFilter, usize)> {
+    let (filter, page) = param.split_once(':').unwrap_or((param, "0"));
+    Ok((filter.parse()?, page.parse()?))
+}
+
+/// What a select menu offered, so that submitting it only changes those games
+#[derive(Clone, Debug)]
+pub(crate) struct OfferedGames {
+    pub(crate) filter: GameFilter,
+    pub(crate) page: usize,
+    pub(crate) roles: Vec<RoleId>,
+}
+
+struct MenuEntry {
+    role_id: RoleId,
+    name: String,
+    parent_name: String,
+    game: Game,
+}
+
+fn menu_entries(guild: &Guild, games: &BTreeMap<RoleId, Game>, filter: GameFilter) -> Vec<MenuEntry> {
+    games
+        .iter()
+        .filter(|(_, game)| filter == GameFilter::All || filter == GameFilter::Parent(game.parent_role))
+        .filter_map(|(role_id, game)| {
+            Some(MenuEntry {
+                role_id: *role_id,
+                name: guild.roles.get(role_id)?.name.clone(),
+                parent_name: guild
+                    .roles
+                    .get(&game.parent_role)
+                    .map_or(game.parent_role.to_string(), |r| r.name.clone()),
+                game: game.clone(),
+            })
+        })
+        .sorted_by(|a, b| (&a.parent_name, &a.name).cmp(&(&b.parent_name, &b.name)))
+        .collect_vec()
+}
+
+fn page_of(entries: Vec<MenuEntry>, page: usize) -> (usize, usize, Vec<MenuEntry>) {
+    let page_count = entries.len().div_ceil(GAMES_PER_PAGE).max(1);
+    let page = page.min(page_count - 1);
+    let entries = entries.into_iter().skip(page * GAMES_PER_PAGE).take(GAMES_PER_PAGE).collect_vec();
+    (page, page_count, entries)
+}
+
+/// Build a page of the game role menu in response to the interaction `key`
+pub(crate) async fn game_role_menu(
+    cache: &Cache,
+    data: &(impl With<ConfigT> + State<StateT>),
+    guild_id: GuildId,
+    user_id: UserId,
+    key: InteractionId,
+    filter: GameFilter,
+    page: usize,
+) -> Result<CreateReply> {
+    let games = data.with_ok(|cfg| cfg.games.clone()).await?;
+    let entries = {
+        let guild = cache.guild(guild_id).some()?;
+        menu_entries(&guild, &games, filter)
+    };
+    match filter {
+        GameFilter::All => ensure!(!entries.is_empty(), "There are no game roles"),
+        GameFilter::Parent(parent_role) => {
+            ensure!(!entries.is_empty(), "There are no game roles for {}", parent_role.mention())
+        }
+    }
+
+    let (page, page_count, entries) = page_of(entries, page);
+    let roles = entries.iter().map(|entry| entry.role_id).collect();
+    data.state().offered_games.insert(key, OfferedGames { filter, page, roles });
+
+    let mut embed = CreateEmbed::new().title("Games").colour(Colour::GOLD);
+    for (parent_name, group) in &entries.iter().chunk_by(|entry| &entry.parent_name) {
+        let mut lines = group
+            .map(|entry| {
+                let bell = if entry.game.opted_out_users.contains(&user_id) { '🔕' } else { '🔔' };
+                format!("{bell} {}", entry.name)
+            })
+            .join("\n");
+        if lines.len() > 1024 {
+            lines.truncate(lines.floor_char_boundary(1023));
+            lines.push('…');
+        }
+        embed = embed.field(parent_name, lines, true);
+    }
+    if page_count > 1 {
+        embed = embed.footer(CreateEmbedFooter::new(format!("Page {}/{page_count}", page + 1)));
+    }
+
+    let options = entries
+        .into_iter()
+        .map(|entry| {
+            let mut option = CreateSelectMenuOption::new(entry.name, entry.role_id.to_string())
+                .default_selection(!entry.game.opted_out_users.contains(&user_id));
+            if let Some(mut description) = entry.game.defaults.description {
+                if description.len() > 100 {
+                    description.truncate(description.floor_char_boundary(97));
+                    description.push('…');
+                }
+                option = option.description(description);
+            }
+            option
+        })
+        .collect_vec();
+
+    let max_values = options.len() as u8;
+    let mut components = vec![CreateActionRow::SelectMenu(
+        CreateSelectMenu::new(format!("{SUBMIT_GAME_ROLES_SELECT_ID}:{key}"), CreateSelectMenuKind::String { options })
+            .min_values(0)
+            .max_values(max_values)
+            .placeholder("Subscribed games"),
+    )];
+    if page_count > 1 {
+        components.push(CreateActionRow::Buttons(vec![
+            CreateButton::new(format!("{GAME_ROLES_PAGE_BUTTON_ID}:{filter}:{}", page.saturating_sub(1)))
+                .style(ButtonStyle::Secondary)
+                .label("Previous")
+                .disabled(page == 0),
+            CreateButton::new(format!("{GAME_ROLES_PAGE_BUTTON_ID}:{filter}:{}", page + 1))
+                .style(ButtonStyle::Secondary)
+                .label("Next")
+                .disabled(page + 1 == page_count),
+        ]));
+    }
+
+    Ok(CreateReply::new().embed(embed).components(components))
+}
@@ -6,7 +6,11 @@ mod buttons;
 mod cmd_ask;
 mod cmd_configure_ask_game;
 mod cmd_delete_ask_game;
+mod cmd_games;
+mod cmd_import_ask_games;
+mod game_role_menu;
 mod schedule_updates;
+mod teams;
 mod worker_ask_update;
 mod worker_game_roles;
 
@@ -15,7 +19,10 @@ pub use crate::buttons::*;
 pub use crate::cmd_ask::*;
 pub use crate::cmd_configure_ask_game::*;
 pub use crate::cmd_delete_ask_game::*;
+pub use crate::cmd_games::*;
+pub use crate::cmd_import_ask_games::*;
 use crate::schedule_updates::schedule_ask_updates;
+use bot_core::component_store::ComponentStore;
 use bot_core::serde::LiteralRegex;
 use bot_core::{State, With};
 use chrono::TimeDelta;
@@ -37,6 +44,9 @@ pub const TOGGLE_GAME_ROLE_BUTTON_ID: &str = "ask.toggle_game_role";
 pub const SHOW_PARENT_ROLE_BUTTONS_ID: &str = "ask.show_parent_role_buttons";
 pub const SHOW_GAME_ROLES_SELECT_ID: &str = "ask.show_game_roles_select";
 pub const SUBMIT_GAME_ROLES_SELECT_ID: &str = "ask.submit_game_roles_select";
+pub const GAME_ROLES_PAGE_BUTTON_ID: &str = "ask.game_roles_page";
+pub const SHUFFLE_TEAMS_BUTTON_ID: &str = "ask.shuffle_teams";
+pub const SHUFFLE_TEAMS_SUBMIT_BUTTON_ID: &str = "ask.shuffle_teams_submit";
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, sensible::Default)]
 pub struct ConfigT {
@@ -45,10 +55,13 @@ pub struct ConfigT {
     expiration: TimeDelta,
     games: BTreeMap<RoleId, Game>,
     asks: BTreeMap<MessageId, Ask>,
+    #[serde(default)]
+    teammates: teams::TeammateHistory,
 }
 
 #[derive(Default)]
 pub struct StateT {
+    offered_games: ComponentStore<game_role_menu::OfferedGames>,
     ask_update_sender: OnceCell<mpsc::Sender<worker_ask_update::Command>>,
     game_role_sender: OnceCell<mpsc::Sender<worker_game_roles::Command>>,
     serpapi_token: OnceCell<String>,
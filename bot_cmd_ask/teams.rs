@@ -0,0 +1,101 @@
+use itertools::Itertools as _;
+use poise::serenity_prelude::UserId;
+use rand::seq::SliceRandom as _;
+use std::collections::BTreeMap;
+
+/// How often two players have recently been put on the same team (stored for both players)
+pub(crate) type TeammateHistory = BTreeMap<UserId, BTreeMap<UserId, u32>>;
+
+const SHUFFLE_ATTEMPTS: usize = 100;
+/// Most shuffles a pairing is remembered for, every shuffle that splits the two players forgets one
+const PAIRING_MEMORY: u32 = 5;
+
+/// Randomly split players into `n` teams of (almost) equal size.
+/// Out of a few random splits, the one repeating the fewest past teammate pairings wins.
+pub(crate) fn shuffle_teams(players: &[UserId], n: usize, history: &TeammateHistory) -> Vec<Vec<UserId>> {
+    let mut rng = rand::rng();
+    (0..SHUFFLE_ATTEMPTS)
+        .map(|_| {
+            let mut players = players.to_vec();
+            players.shuffle(&mut rng);
+            deal(players, n)
+        })
+        .min_by_key(|teams| repeated_pairings(teams, history))
+        .unwrap_or_default()
+}
+
+pub(crate) fn record_teams(history: &mut TeammateHistory, teams: &[Vec<UserId>]) {
+    let team_of = teams
+        .iter()
+        .enumerate()
+        .flat_map(|(i, team)| team.iter().map(move |&player| (player, i)))
+        .collect::<BTreeMap<_, _>>();
+    for (&a, &b) in team_of.keys().tuple_combinations() {
+        let together = team_of[&a] == team_of[&b];
+        for (player, other) in [(a, b), (b, a)] {
+            let counts = history.entry(player).or_default();
+            let count = counts.entry(other).or_default();
+            *count = if together { (*count + 1).min(PAIRING_MEMORY) } else { count.saturating_sub(1) };
+            if *count == 0 {
+                counts.remove(&other);
+            }
+        }
+    }
+    history.retain(|_, counts| !counts.is_empty());
+}
+
+fn deal(players: Vec<UserId>, n: usize) -> Vec<Vec<UserId>> {
+    let mut teams = vec![vec![]; n];
+    for (i, player) in players.into_iter().enumerate() {
+        teams[i % n].push(player);
+    }
+    teams
+}
+
+fn repeated_pairings(teams: &[Vec<UserId>], history: &TeammateHistory) -> u32 {
+    teams
+        .iter()
+        .flat_map(|team| team.iter().tuple_combinations())
+        .map(|(a, b)| history.get(a).and_then(|h| h.get(b)).copied().unwrap_or_default())
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn teams_are_balanced_and_complete() {
+        let players = (1..=7).map(UserId::new).collect_vec();
+        let teams = shuffle_teams(&players, 3, &Default::default());
+        assert_eq!(teams.iter().map(Vec::len).sorted().collect_vec(), vec![2, 2, 3]);
+        assert_eq!(teams.into_iter().flatten().sorted().collect_vec(), players);
+    }
+
+    #[test]
+    fn avoids_repeated_teammates() {
+        let players = (1..=4).map(UserId::new).collect_vec();
+        let mut history = TeammateHistory::new();
+        record_teams(&mut history, &[vec![players[0], players[1]], vec![players[2], players[3]]]);
+        let teams = shuffle_teams(&players, 2, &history);
+        assert_eq!(repeated_pairings(&teams, &history), 0);
+    }
+
+    #[test]
+    fn forgets_old_pairings() {
+        let players = (1..=4).map(UserId::new).collect_vec();
+        let together = [vec![players[0], players[1]], vec![players[2], players[3]]];
+        let apart = [vec![players[0], players[2]], vec![players[1], players[3]]];
+        let mut history = TeammateHistory::new();
+        for _ in 0..10 {
+            record_teams(&mut history, &together);
+        }
+        assert_eq!(history[&players[0]][&players[1]], PAIRING_MEMORY);
+
+        for _ in 0..PAIRING_MEMORY {
+            record_teams(&mut history, &apart);
+        }
+        assert!(!history[&players[0]].contains_key(&players[1]));
+        assert_eq!(history[&players[0]][&players[2]], PAIRING_MEMORY);
+    }
+}
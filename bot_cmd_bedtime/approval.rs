@@ -0,0 +1,85 @@
+use crate::ConfigT;
+use bot_core::ext::create_reply::CreateReplyExt;
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    ButtonStyle, Color, ComponentInteraction, Context, CreateActionRow, CreateButton, CreateEmbed, CreateMessage,
+    Mentionable as _, UserId,
+};
+use std::time::Duration;
+
+/// The partner who has to approve changes to a user's bedtimes right now, i.e. during an active bedtime
+pub(crate) fn required_approval(cfg: &ConfigT, user_id: UserId, now: DateTime<Utc>) -> Option<UserId> {
+    let partner = cfg.partners.get(&user_id)?;
+    cfg.intervals(now).get(&user_id)?.find(now)?;
+    Some(*partner)
+}
+
+/// Ask the accountability partner to approve an action of the user who pressed the button.
+/// Responds to the component and returns whether the partner approved.
+pub(crate) async fn partner_approval(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    partner: UserId,
+    action: &str,
+) -> Result<bool> {
+    CreateReply::new()
+        .content(format!("⏳ Waiting for {} to approve", partner.mention()))
+        .ephemeral(true)
+        .respond_to_component(ctx, component)
+        .await?;
+
+    let approve_id = "~bedtime.approve";
+    let deny_id = "~bedtime.deny";
+
+    let embed = CreateEmbed::new()
+        .title("🌙 Bedtime")
+        .description(format!("{} wants to {action}", component.user.mention()))
+        .color(Color::DARK_PURPLE);
+    let request = CreateMessage::new().content(partner.mention().to_string()).embed(embed.clone()).components(vec![
+        CreateActionRow::Buttons(vec![
+            CreateButton::new(approve_id).label("Approve").style(ButtonStyle::Success),
+            CreateButton::new(deny_id).label("Deny").style(ButtonStyle::Danger),
+        ]),
+    ]);
+
+    // the partner can't see the user's DMs with the bot
+    let message = match component.guild_id {
+        Some(_) => component.channel_id.send_message(ctx, request).await?,
+        None => partner.direct_message(ctx, request).await?,
+    };
+
+    let interaction = async {
+        while let Some(interaction) =
+            message.await_component_interaction(ctx).timeout(Duration::from_secs(5 * 60)).await
+        {
+            if interaction.user.id == partner {
+                return Some(interaction);
+            }
+        }
+        None
+    }
+    .await;
+
+    let approved = interaction.as_ref().is_some_and(|i| i.data.custom_id == approve_id);
+
+    // deactivate the request in all cases
+    let reply = CreateReply::new()
+        .embed(embed.color(if approved { Color::DARK_GREEN } else { Color::DARKER_GREY }))
+        .components(vec![]);
+    match &interaction {
+        Some(interaction) => reply.update_to_component(ctx, interaction).await?,
+        None => _ = reply.edit_message(ctx, &message).await?,
+    }
+
+    if !approved {
+        CreateReply::new()
+            .content(format!("🙅 {} didn't approve", partner.mention()))
+            .ephemeral(true)
+            .followup_to_component(ctx, component)
+            .await?;
+    }
+
+    Ok(approved)
+}
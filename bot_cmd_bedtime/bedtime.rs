@@ -1,16 +1,22 @@
-use crate::{ConfigT, DELETE_BUTTON_ID, SELECT_BEDTIME_ID, TOGGLE_WEEKDAY_BUTTON_ID};
+use crate::restrictions::Restriction;
+use crate::snooze::snooze_button;
+use crate::{
+    ConfigT, DELETE_BUTTON_ID, END_BUTTON_ID, RESTRICTION_BUTTON_ID, SELECT_BEDTIME_ID, TOGGLE_WEEKDAY_BUTTON_ID,
+};
 use bot_core::With;
+use bot_core::time::human::{parse_duration, parse_time};
 use bot_core::time::iso_weekday::IsoWeekday;
-use chrono::{DateTime, Datelike, Days, Local, TimeDelta, TimeZone, Utc, Weekday};
-use eyre::Result;
+use bot_core::time::{next_datetime_at, resolve_local};
+use chrono::{DateTime, Datelike, NaiveTime, TimeDelta, Utc, Weekday};
+use chrono_tz::Tz;
+use eyre::{OptionExt as _, Result, bail, ensure};
 use itertools::Itertools as _;
 use poise::CreateReply;
 use poise::serenity_prelude::{
-    ButtonStyle, Color, CreateActionRow, CreateButton, CreateEmbed, CreateSelectMenu, CreateSelectMenuKind,
-    CreateSelectMenuOption, ReactionType, UserId,
+    ButtonStyle, Color, CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, CreateSelectMenu,
+    CreateSelectMenuKind, CreateSelectMenuOption, Mentionable as _, ReactionType, UserId,
 };
 use std::collections::{BTreeMap, BTreeSet};
-use std::fmt::Display;
 use std::iter;
 use uuid::Uuid;
 
@@ -19,31 +25,122 @@ pub(crate) struct Bedtime {
     pub(crate) user: UserId,
     pub(crate) first: DateTime<Utc>,
     pub(crate) repeat: BTreeSet<IsoWeekday>,
+    /// Defaults to the configured duration
+    #[serde(default)]
+    pub(crate) end: Option<BedtimeEnd>,
+    #[serde(default)]
+    pub(crate) restrictions: BTreeSet<Restriction>,
+    /// The moderator who set the bedtime for the user
+    #[serde(default)]
+    pub(crate) set_by: Option<UserId>,
+    /// Only moderators can change or delete locked bedtimes
+    #[serde(default)]
+    pub(crate) locked: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BedtimeEnd {
+    /// Wall clock time in the user's timezone
+    WakeUp(NaiveTime),
+    Duration(#[serde(with = "bot_core::serde::td_seconds")] TimeDelta),
+}
+
+impl BedtimeEnd {
+    /// Parses a wake-up time like `07:30` or a duration like `8h30m`, nothing means the default duration
+    pub(crate) fn parse(input: &str) -> Result<Option<Self>> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(None);
+        }
+        if let Some(time) = parse_time(input) {
+            return Ok(Some(BedtimeEnd::WakeUp(time)));
+        }
+        let duration =
+            parse_duration(input).ok_or_eyre("Expected a wake-up time like 07:30 or a duration like 8h30m")?;
+        ensure!(
+            duration > TimeDelta::zero() && duration <= TimeDelta::days(1),
+            "A bedtime has to last between 0 and 24 hours"
+        );
+        Ok(Some(BedtimeEnd::Duration(duration)))
+    }
+}
+
+impl std::fmt::Display for BedtimeEnd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BedtimeEnd::WakeUp(time) => write!(f, "{}", time.format("%H:%M")),
+            BedtimeEnd::Duration(duration) => {
+                write!(f, "{}h{:02}m", duration.num_hours(), duration.num_minutes() % 60)
+            }
+        }
+    }
 }
 
 impl Bedtime {
-    pub(crate) fn currently_relevant_bedtimes(&self, now: DateTime<Utc>) -> BTreeSet<DateTime<Utc>> {
-        let today = now.date_naive().and_time(self.first.time()).and_utc();
-        let repeats = [today - Days::new(1), today]
-            .into_iter()
-            .chain((1..=7).map(|offset| today + Days::new(offset)))
-            .filter(|&bedtime| bedtime > self.first)
-            .filter(|&bedtime| self.repeat.contains(&IsoWeekday(bedtime.weekday())));
+    /// Repeats happen at the same wall clock time in `tz` as the first bedtime, on the weekdays of that timezone
+    pub(crate) fn currently_relevant_bedtimes(&self, now: DateTime<Utc>, tz: Tz) -> BTreeSet<DateTime<Utc>> {
+        let time = self.first.with_timezone(&tz).time();
+        let today = now.with_timezone(&tz).date_naive();
+        let repeats = (-1..=7)
+            .filter_map(|offset| today.checked_add_signed(TimeDelta::days(offset)))
+            .filter(|date| self.repeat.contains(&IsoWeekday(date.weekday())))
+            .filter_map(|date| resolve_local(&tz, date.and_time(time)))
+            .map(|bedtime| bedtime.to_utc())
+            .filter(|&bedtime| bedtime > self.first);
         iter::once(self.first).chain(repeats).collect()
     }
 
-    pub(crate) fn next(&self, now: DateTime<Utc>) -> DateTime<Utc> {
-        self.currently_relevant_bedtimes(now).into_iter().find(|&bedtime| bedtime > now).unwrap_or(self.first)
+    pub(crate) fn ensure_visible_to(&self, user_id: UserId, moderator: bool) -> Result<()> {
+        ensure!(moderator || user_id == self.user, "That's not your own bedtime");
+        Ok(())
+    }
+
+    pub(crate) fn ensure_changeable_by(&self, user_id: UserId, moderator: bool) -> Result<()> {
+        self.ensure_visible_to(user_id, moderator)?;
+        if !moderator && self.locked {
+            let by = self.set_by.map_or("a moderator".to_string(), |id| id.mention().to_string());
+            bail!("This bedtime was locked by {by}");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn next(&self, now: DateTime<Utc>, tz: Tz) -> DateTime<Utc> {
+        self.currently_relevant_bedtimes(now, tz).into_iter().find(|&bedtime| bedtime > now).unwrap_or(self.first)
+    }
+
+    /// When the bedtime starting at `start` ends
+    pub(crate) fn end_of(&self, start: DateTime<Utc>, tz: Tz, default_duration: TimeDelta) -> DateTime<Utc> {
+        match self.end {
+            Some(BedtimeEnd::WakeUp(time)) => {
+                next_datetime_at(&tz, time, start).map_or(start + default_duration, |end| end.to_utc())
+            }
+            Some(BedtimeEnd::Duration(duration)) => start + duration,
+            None => start + default_duration,
+        }
     }
 
     pub(crate) async fn reply(&self, id: Uuid, data: &impl With<ConfigT>, now: DateTime<Utc>) -> Result<CreateReply> {
-        Ok(CreateReply::new().embed(self.embed(now)).components(self.components(id, data, now).await?))
+        let (tz, duration) = data.with_ok(|cfg| (cfg.timezone(self.user), cfg.duration)).await?;
+        Ok(CreateReply::new().embed(self.embed(now, tz, duration)).components(self.components(id, data, now).await?))
     }
 
-    pub(crate) fn embed(&self, now: DateTime<Utc>) -> CreateEmbed {
+    pub(crate) fn embed(&self, now: DateTime<Utc>, tz: Tz, default_duration: TimeDelta) -> CreateEmbed {
+        let next = self.next(now, tz);
+        let end = self.end_of(next, tz, default_duration).with_timezone(&tz);
         CreateEmbed::new()
-            .title(format!("🌙 Bedtime – {}", format_datetime(self.next(now), now, &Local)))
-            .description(format!("<t:{}:R>", self.next(Utc::now()).timestamp()))
+            .title(format!("🌙 Bedtime – {} until {}", format_datetime(next, now, tz), end.format("%H:%M")))
+            .description(match self.set_by {
+                Some(set_by) => format!(
+                    "<t:{}:R> for {}\nSet by {}{}",
+                    next.timestamp(),
+                    self.user.mention(),
+                    set_by.mention(),
+                    if self.locked { " 🔒" } else { "" }
+                ),
+                None => format!("<t:{}:R>", next.timestamp()),
+            })
+            .footer(CreateEmbedFooter::new(tz.name()))
             .color(Color::DARK_PURPLE)
     }
 
@@ -69,8 +166,16 @@ impl Bedtime {
             CreateButton::new(format!("{DELETE_BUTTON_ID}:{id}"))
                 .style(ButtonStyle::Danger)
                 .emoji(ReactionType::Unicode("🗑️".to_string())),
+            snooze_button(self.user),
+            CreateButton::new(format!("{END_BUTTON_ID}:{id}"))
+                .style(ButtonStyle::Secondary)
+                .emoji(ReactionType::Unicode("⏰".to_string())),
         ]));
 
+        components.push(CreateActionRow::Buttons(
+            Restriction::ALL.into_iter().map(|restriction| self.restriction_button(id, restriction)).collect(),
+        ));
+
         Ok(components)
     }
 
@@ -83,13 +188,14 @@ impl Bedtime {
         let mut components = vec![];
 
         // add a selection menu to view other bedtimes
+        let tz = data.with_ok(|cfg| cfg.timezone(self.user)).await?;
         let other_bedtimes: BTreeSet<_> =
             all_bedtimes(data, self.user).await?.into_iter().filter(|(other_id, _)| *other_id != id).collect();
         if !other_bedtimes.is_empty() {
             let options = other_bedtimes
                 .into_iter()
                 .map(|(other_id, bedtime)| {
-                    CreateSelectMenuOption::new(format_datetime(bedtime.next(now), now, &Local), other_id).description(
+                    CreateSelectMenuOption::new(format_datetime(bedtime.next(now, tz), now, tz), other_id).description(
                         if !bedtime.repeat.is_empty() {
                             let repeats = bedtime.repeat.iter().map(|wd| wd.0.to_string()).join(", ");
                             format!("Repeats on: {repeats}")
@@ -121,6 +227,12 @@ impl Bedtime {
             })
             .label(weekday_str)
     }
+
+    fn restriction_button(&self, id: Uuid, restriction: Restriction) -> CreateButton {
+        CreateButton::new(format!("{RESTRICTION_BUTTON_ID}:{id}:{}", restriction.name()))
+            .style(if self.restrictions.contains(&restriction) { ButtonStyle::Primary } else { ButtonStyle::Secondary })
+            .label(restriction.label())
+    }
 }
 
 async fn all_bedtimes(data: &impl With<ConfigT>, user_id: UserId) -> Result<BTreeMap<Uuid, Bedtime>> {
@@ -128,30 +240,26 @@ async fn all_bedtimes(data: &impl With<ConfigT>, user_id: UserId) -> Result<BTre
         .await
 }
 
-fn format_datetime<TZ>(dt: DateTime<Utc>, now: DateTime<Utc>, display_tz: &TZ) -> String
-where
-    TZ: TimeZone,
-    TZ::Offset: Display,
-{
-    dt.with_timezone(display_tz)
-        .format(if dt.num_days_from_ce() == now.num_days_from_ce() || dt < now + TimeDelta::hours(12) {
-            "%H:%M"
-        } else if dt.num_days_from_ce() - now.num_days_from_ce() == 1 {
-            "Tomorrow at %H:%M"
-        } else if dt < now + TimeDelta::weeks(1) {
-            "%A at %H:%M"
-        } else if dt.year() == now.year() {
-            "%A, %d.%m. at %H:%M"
-        } else {
-            "%A, %d.%m.%Y at %H:%M"
-        })
-        .to_string()
+pub(crate) fn format_datetime(dt: DateTime<Utc>, now: DateTime<Utc>, tz: Tz) -> String {
+    let (dt, now) = (dt.with_timezone(&tz), now.with_timezone(&tz));
+    dt.format(if dt.num_days_from_ce() == now.num_days_from_ce() || dt < now + TimeDelta::hours(12) {
+        "%H:%M"
+    } else if dt.num_days_from_ce() - now.num_days_from_ce() == 1 {
+        "Tomorrow at %H:%M"
+    } else if dt < now + TimeDelta::weeks(1) {
+        "%A at %H:%M"
+    } else if dt.year() == now.year() {
+        "%A, %d.%m. at %H:%M"
+    } else {
+        "%A, %d.%m.%Y at %H:%M"
+    })
+    .to_string()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use chrono::{TimeDelta, TimeZone};
+    use chrono::TimeZone;
 
     #[test]
     fn currently_relevant_bedtimes_when_after_first() {
@@ -160,9 +268,13 @@ mod test {
             user: Default::default(),
             first: today - TimeDelta::days(10),
             repeat: [Weekday::Tue, Weekday::Wed, Weekday::Sun].into_iter().map(IsoWeekday).collect(),
+            end: None,
+            restrictions: Default::default(),
+            set_by: None,
+            locked: false,
         };
         assert_eq!(
-            bedtime2.currently_relevant_bedtimes(today - TimeDelta::minutes(1)),
+            bedtime2.currently_relevant_bedtimes(today - TimeDelta::minutes(1), Tz::UTC),
             [
                 Utc.with_ymd_and_hms(2024, 12, 22, 1, 15, 0).unwrap(),
                 Utc.with_ymd_and_hms(2024, 12, 31, 1, 15, 0).unwrap(),
@@ -183,9 +295,13 @@ mod test {
             user: Default::default(),
             first: today,
             repeat: [Weekday::Tue, Weekday::Wed, Weekday::Sun].into_iter().map(IsoWeekday).collect(),
+            end: None,
+            restrictions: Default::default(),
+            set_by: None,
+            locked: false,
         };
         assert_eq!(
-            bedtime.currently_relevant_bedtimes(today - TimeDelta::minutes(1)),
+            bedtime.currently_relevant_bedtimes(today - TimeDelta::minutes(1), Tz::UTC),
             [
                 Utc.with_ymd_and_hms(2025, 1, 1, 1, 15, 0).unwrap(),
                 Utc.with_ymd_and_hms(2025, 1, 5, 1, 15, 0).unwrap(),
@@ -196,4 +312,43 @@ mod test {
             .collect::<BTreeSet<_>>()
         );
     }
+
+    #[test]
+    fn currently_relevant_bedtimes_across_dst_change() {
+        // 23:00 in Berlin is 22:00 UTC in winter and 21:00 UTC in summer
+        let tz = chrono_tz::Europe::Berlin;
+        let bedtime = Bedtime {
+            user: Default::default(),
+            first: tz.with_ymd_and_hms(2025, 3, 27, 23, 0, 0).unwrap().to_utc(),
+            repeat: [Weekday::Sat, Weekday::Sun].into_iter().map(IsoWeekday).collect(),
+            end: None,
+            restrictions: Default::default(),
+            set_by: None,
+            locked: false,
+        };
+        assert_eq!(
+            bedtime.currently_relevant_bedtimes(Utc.with_ymd_and_hms(2025, 3, 28, 12, 0, 0).unwrap(), tz),
+            [
+                Utc.with_ymd_and_hms(2025, 3, 27, 22, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2025, 3, 29, 22, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2025, 3, 30, 21, 0, 0).unwrap(),
+            ]
+            .into_iter()
+            .collect::<BTreeSet<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_bedtime_end() {
+        assert_eq!(BedtimeEnd::parse("").unwrap(), None);
+        assert_eq!(
+            BedtimeEnd::parse("07:30").unwrap(),
+            Some(BedtimeEnd::WakeUp(NaiveTime::from_hms_opt(7, 30, 0).unwrap()))
+        );
+        assert_eq!(BedtimeEnd::parse("8h").unwrap(), Some(BedtimeEnd::Duration(TimeDelta::hours(8))));
+        assert_eq!(BedtimeEnd::parse("7h 30m").unwrap(), Some(BedtimeEnd::Duration(TimeDelta::minutes(450))));
+        assert_eq!(BedtimeEnd::parse("90m").unwrap(), Some(BedtimeEnd::Duration(TimeDelta::minutes(90))));
+        assert!(BedtimeEnd::parse("25h").is_err());
+        assert!(BedtimeEnd::parse("soon").is_err());
+    }
 }
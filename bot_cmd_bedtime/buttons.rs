@@ -1,12 +1,19 @@
-use crate::ConfigT;
+use crate::approval::{partner_approval, required_approval};
+use crate::bedtime::BedtimeEnd;
+use crate::restrictions::Restriction;
+use crate::{ConfigT, is_moderator};
 use bot_core::ext::create_reply::CreateReplyExt;
 use bot_core::ext::option::OptionExt as _;
 use bot_core::time::iso_weekday::IsoWeekday;
 use bot_core::{EvtContext, With};
 use chrono::{Utc, Weekday};
-use eyre::{OptionExt, Result, ensure};
+use eyre::{OptionExt, Result, bail};
 use poise::CreateReply;
-use poise::serenity_prelude::{Color, ComponentInteraction, ComponentInteractionDataKind};
+use poise::serenity_prelude::{
+    Color, ComponentInteraction, ComponentInteractionDataKind, CreateInputText, CreateQuickModal, InputTextStyle,
+    Mentionable as _,
+};
+use std::time::Duration;
 use uuid::Uuid;
 
 pub async fn btn_toggle_weekday_button(
@@ -18,14 +25,16 @@ pub async fn btn_toggle_weekday_button(
     let id = Uuid::try_parse(id_str)?;
     let weekday = IsoWeekday(weekday_str.parse::<Weekday>()?);
 
-    component.defer(ctx.serenity_context).await?;
+    if !defer_or_approve(&ctx, component, id, "change their bedtime").await? {
+        return Ok(());
+    }
 
     tracing::info!("Toggling {} on bedtime {id}", weekday.0);
     let bedtime = ctx
         .user_data
         .with_mut(|cfg| {
             let bedtime = cfg.bedtimes.get_mut(&id).ok_or_eyre("Bedtime no longer exists")?;
-            ensure!(component.user.id == bedtime.user, "That's not your own bedtime");
+            bedtime.ensure_changeable_by(component.user.id, is_moderator(component.member.as_deref()))?;
             if !bedtime.repeat.remove(&weekday) {
                 bedtime.repeat.insert(weekday);
             }
@@ -33,7 +42,46 @@ pub async fn btn_toggle_weekday_button(
         })
         .await?;
 
-    bedtime.reply(id, ctx.user_data, Utc::now()).await?.edit_message(ctx.serenity_context, &component.message).await?;
+    bedtime
+        .reply(id, ctx.user_data, Utc::now())
+        .await?
+        .edit_interaction_message(ctx.serenity_context, &component.message, &component.token)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn btn_toggle_restriction(
+    ctx: EvtContext<'_, impl With<ConfigT>>,
+    component: &ComponentInteraction,
+    param: &str,
+) -> Result<()> {
+    let (id_str, restriction_str) = param.split_once(":").ok_or_eyre("Expected a restriction parameter")?;
+    let id = Uuid::try_parse(id_str)?;
+    let restriction = Restriction::from_name(restriction_str).ok_or_eyre("Unknown restriction")?;
+
+    if !defer_or_approve(&ctx, component, id, "change their bedtime").await? {
+        return Ok(());
+    }
+
+    tracing::info!("Toggling {restriction:?} on bedtime {id}");
+    let bedtime = ctx
+        .user_data
+        .with_mut(|cfg| {
+            let bedtime = cfg.bedtimes.get_mut(&id).ok_or_eyre("Bedtime no longer exists")?;
+            bedtime.ensure_changeable_by(component.user.id, is_moderator(component.member.as_deref()))?;
+            if !bedtime.restrictions.remove(&restriction) {
+                bedtime.restrictions.insert(restriction);
+            }
+            Ok(bedtime.clone())
+        })
+        .await?;
+
+    bedtime
+        .reply(id, ctx.user_data, Utc::now())
+        .await?
+        .edit_interaction_message(ctx.serenity_context, &component.message, &component.token)
+        .await?;
 
     Ok(())
 }
@@ -45,29 +93,96 @@ pub async fn btn_delete(
 ) -> Result<()> {
     let id = Uuid::try_parse(param)?;
 
-    component.defer(ctx.serenity_context).await?;
+    if !defer_or_approve(&ctx, component, id, "delete their bedtime").await? {
+        return Ok(());
+    }
 
     tracing::info!("Removing bedtime {id}");
     let bedtime = ctx
         .user_data
         .with_mut(|cfg| {
             let bedtime = cfg.bedtimes.get(&id).cloned().ok_or_eyre("Bedtime no longer exists")?;
-            ensure!(component.user.id == bedtime.user, "That's not your own bedtime");
+            bedtime.ensure_changeable_by(component.user.id, is_moderator(component.member.as_deref()))?;
             cfg.bedtimes.remove(&id);
             Ok(bedtime)
         })
         .await?;
 
     let now = Utc::now();
+    let (tz, duration) = ctx.user_data.with_ok(|cfg| (cfg.timezone(bedtime.user), cfg.duration)).await?;
     CreateReply::new()
-        .embed(bedtime.embed(now).color(Color::DARKER_GREY))
+        .embed(bedtime.embed(now, tz, duration).color(Color::DARKER_GREY))
         .components(bedtime.select_menu_component(id, ctx.user_data, now).await?)
-        .edit_message(ctx.serenity_context, &component.message)
+        .edit_interaction_message(ctx.serenity_context, &component.message, &component.token)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn btn_edit_end(
+    ctx: EvtContext<'_, impl With<ConfigT>>,
+    component: &ComponentInteraction,
+    param: &str,
+) -> Result<()> {
+    let id = Uuid::try_parse(param)?;
+
+    let current = ctx
+        .user_data
+        .with(|cfg| {
+            ensure_end_editable(cfg, id, component)?;
+            Ok(cfg.bedtimes[&id].end)
+        })
+        .await?;
+
+    let Some(response) = CreateQuickModal::new("Bedtime")
+        .field(
+            CreateInputText::new(InputTextStyle::Short, "Wake-up time (07:30) or duration (8h30m)", "")
+                .value(current.map(|end| end.to_string()).unwrap_or_default())
+                .required(false),
+        )
+        .timeout(Duration::from_secs(10 * 60))
+        .execute(ctx.serenity_context, component.id, &component.token)
+        .await?
+    else {
+        return Ok(());
+    };
+
+    let end = BedtimeEnd::parse(response.inputs.first().some()?)?;
+    response.interaction.defer(ctx.serenity_context).await?;
+
+    tracing::info!("Setting end of bedtime {id} to {end:?}");
+    let bedtime = ctx
+        .user_data
+        .with_mut(|cfg| {
+            // the bedtime may have been locked or started while the modal was open
+            ensure_end_editable(cfg, id, component)?;
+            let bedtime = cfg.bedtimes.get_mut(&id).some()?;
+            bedtime.end = end;
+            Ok(bedtime.clone())
+        })
+        .await?;
+
+    bedtime
+        .reply(id, ctx.user_data, Utc::now())
+        .await?
+        .edit_interaction_message(ctx.serenity_context, &component.message, &response.interaction.token)
         .await?;
 
     Ok(())
 }
 
+fn ensure_end_editable(cfg: &ConfigT, id: Uuid, component: &ComponentInteraction) -> Result<()> {
+    let bedtime = cfg.bedtimes.get(&id).ok_or_eyre("Bedtime no longer exists")?;
+    bedtime.ensure_changeable_by(component.user.id, is_moderator(component.member.as_deref()))?;
+    // the modal has to be the response, so there's no way to ask the partner first
+    if component.user.id == bedtime.user
+        && let Some(partner) = required_approval(cfg, bedtime.user, Utc::now())
+    {
+        bail!("Your bedtime has already started, ask {} to wait until it's over", partner.mention());
+    }
+    Ok(())
+}
+
 pub async fn btn_select_bedtime(
     ctx: EvtContext<'_, impl With<ConfigT>>,
     component: &ComponentInteraction,
@@ -84,12 +199,43 @@ pub async fn btn_select_bedtime(
         .user_data
         .with(|cfg| {
             let bedtime = cfg.bedtimes.get(&id).cloned().ok_or_eyre("Bedtime no longer exists")?;
-            ensure!(component.user.id == bedtime.user, "That's not your own bedtime");
+            bedtime.ensure_visible_to(component.user.id, is_moderator(component.member.as_deref()))?;
             Ok(bedtime)
         })
         .await?;
 
-    bedtime.reply(id, ctx.user_data, Utc::now()).await?.edit_message(ctx.serenity_context, &component.message).await?;
+    bedtime
+        .reply(id, ctx.user_data, Utc::now())
+        .await?
+        .edit_interaction_message(ctx.serenity_context, &component.message, &component.token)
+        .await?;
 
     Ok(())
 }
+
+/// Acknowledge the component, but first get the partner's approval if the user currently has bedtime.
+/// Returns whether the action may go ahead.
+async fn defer_or_approve(
+    ctx: &EvtContext<'_, impl With<ConfigT>>,
+    component: &ComponentInteraction,
+    id: Uuid,
+    action: &str,
+) -> Result<bool> {
+    let partner = ctx
+        .user_data
+        .with(|cfg| {
+            let bedtime = cfg.bedtimes.get(&id).ok_or_eyre("Bedtime no longer exists")?;
+            bedtime.ensure_changeable_by(component.user.id, is_moderator(component.member.as_deref()))?;
+            // moderators managing someone else's bedtime don't need the partner's approval
+            Ok(required_approval(cfg, bedtime.user, Utc::now()).filter(|_| component.user.id == bedtime.user))
+        })
+        .await?;
+
+    match partner {
+        Some(partner) => partner_approval(ctx.serenity_context, component, partner, action).await,
+        None => {
+            component.defer(ctx.serenity_context).await?;
+            Ok(true)
+        }
+    }
+}
@@ -1,30 +1,85 @@
-use super::ConfigT;
-use crate::bedtime::Bedtime;
-use bot_core::{CmdContext, With, naive_time_to_next_datetime};
-use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
-use eyre::{OptionExt as _, Result};
+use super::{ConfigT, SELECT_BEDTIME_ID, is_moderator};
+use crate::approval::required_approval;
+use crate::bedtime::{Bedtime, BedtimeEnd, format_datetime};
+use crate::ical;
+use crate::stats::stats_embed;
+use bot_core::time::human::{parse_date, parse_datetime, parse_time};
+use bot_core::time::{local_timezone, resolve_local};
+use bot_core::{CmdContext, With, safe_name};
+use chrono::Utc;
+use chrono_tz::Tz;
+use eyre::{OptionExt as _, Result, bail, ensure, eyre};
+use itertools::Itertools as _;
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    Attachment, Color, CreateActionRow, CreateAttachment, CreateAutocompleteResponse, CreateEmbed, CreateSelectMenu,
+    CreateSelectMenuKind, CreateSelectMenuOption, Mentionable as _, UserId,
+};
 use uuid::Uuid;
 
+// This used to be `/bedtime <time>`, which is now `/bedtime set <time>`:
+// Discord doesn't allow a command to take options and have subcommands at the same time.
+/// Manage your bedtimes
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands(
+        "bedtime_set",
+        "bedtime_timezone",
+        "bedtime_partner",
+        "bedtime_stats",
+        "bedtime_export",
+        "bedtime_import",
+        "bedtime_list"
+    ),
+    subcommand_required
+)]
+pub async fn bedtime<D: With<ConfigT>>(_ctx: CmdContext<'_, D>) -> Result<()> {
+    Ok(())
+}
+
 /// Set a bedtime
-#[poise::command(slash_command, guild_only)]
-pub async fn bedtime<D: With<ConfigT>>(
+#[poise::command(slash_command, guild_only, rename = "set")]
+pub async fn bedtime_set<D: With<ConfigT>>(
     ctx: CmdContext<'_, D>,
-    #[string]
-    #[autocomplete = bot_core::autocomplete::time]
-    #[description = "Time"]
-    time: NaiveTime,
-    #[string]
-    // todo autocomplete
+    #[autocomplete = member_datetime]
+    #[description = "Time, e.g. 23:30, tonight 1am or fri 23:00"]
+    time: String,
+    #[autocomplete = bot_core::autocomplete::date]
     #[description = "Date"]
-    date: Option<NaiveDate>,
+    date: Option<String>,
+    #[autocomplete = bot_core::autocomplete::time]
+    #[description = "Wake-up time, when your bedtime ends"]
+    wake_up: Option<String>,
+    #[description = "Member to set the bedtime for (moderators only)"] user: Option<UserId>,
+    #[description = "Keep the member from changing or deleting it (moderators only)"] locked: Option<bool>,
 ) -> Result<()> {
+    let author = ctx.author().id;
+    let user_id = user.unwrap_or(author);
+    let locked = locked.unwrap_or(false);
+    if user_id != author || locked {
+        ensure!(is_moderator(ctx.author_member().await.as_deref()), "Only moderators can set bedtimes for others");
+    }
+
+    let tz = ctx.data().with_ok(|cfg| cfg.timezone(user_id)).await?;
+    let first = match date {
+        Some(date) => {
+            let date = parse_date(&date, Utc::now().with_timezone(&tz).date_naive()).ok_or_eyre("Invalid date")?;
+            let time = parse_time(&time).ok_or_eyre("With a date, the time has to be a time of day like 23:30")?;
+            resolve_local(&tz, date.and_time(time)).ok_or_eyre("Invalid time")?
+        }
+        None => parse_datetime(&time, &tz, Utc::now())
+            .ok_or_eyre("Invalid time, try something like 23:30, tonight 1am or fri 23:00")?,
+    };
+    let wake_up = wake_up.map(|input| parse_time(&input).ok_or_eyre("Invalid wake-up time")).transpose()?;
     let bedtime = Bedtime {
-        user: ctx.author().id,
-        first: match date {
-            Some(d) => NaiveDateTime::new(d, time).and_utc(),
-            None => naive_time_to_next_datetime(time).ok_or_eyre("Gap in time")?.to_utc(),
-        },
+        user: user_id,
+        first: first.to_utc(),
         repeat: Default::default(),
+        end: wake_up.map(BedtimeEnd::WakeUp),
+        restrictions: Default::default(),
+        set_by: (user_id != author).then_some(author),
+        locked,
     };
 
     let id = ctx
@@ -41,6 +96,221 @@ pub async fn bedtime<D: With<ConfigT>>(
     Ok(())
 }
 
+/// Previews the time in the member's timezone, which is what the bedtime is parsed in
+async fn member_datetime<U, E>(ctx: poise::Context<'_, U, E>, input: &str) -> CreateAutocompleteResponse
+where
+    U: With<ConfigT>,
+{
+    let user_id = ctx.author().id;
+    let tz = ctx.data().with_ok(|cfg| cfg.timezone(user_id)).await.unwrap_or_else(|_| local_timezone());
+    bot_core::autocomplete::datetime_in(input, tz, Utc::now())
+}
+
+/// Set the timezone of your bedtimes
+#[poise::command(slash_command, guild_only, rename = "timezone")]
+pub async fn bedtime_timezone<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[autocomplete = bot_core::autocomplete::timezone]
+    #[description = "Timezone, e.g. Europe/Berlin"]
+    timezone: String,
+) -> Result<()> {
+    let tz: Tz = timezone.parse().map_err(|_| eyre!("Unknown timezone: {timezone}"))?;
+    let user_id = ctx.author().id;
+    let moderator = is_moderator(ctx.author_member().await.as_deref());
+
+    ctx.data()
+        .with_mut(|cfg| {
+            // moving locked bedtimes to another timezone would shift them
+            for bedtime in cfg.bedtimes.values().filter(|bedtime| bedtime.user == user_id) {
+                bedtime.ensure_changeable_by(user_id, moderator)?;
+            }
+            let old_tz = cfg.timezone(user_id);
+            cfg.timezones.insert(user_id, tz);
+            // keep the wall clock times of existing bedtimes
+            for bedtime in cfg.bedtimes.values_mut().filter(|bedtime| bedtime.user == user_id) {
+                if let Some(first) = resolve_local(&tz, bedtime.first.with_timezone(&old_tz).naive_local()) {
+                    bedtime.first = first.to_utc();
+                }
+            }
+            Ok(())
+        })
+        .await?;
+
+    ctx.say(format!("🌍 Your bedtimes are now in {}", tz.name())).await?;
+
+    Ok(())
+}
+
+/// Choose an accountability partner who has to approve snoozes and changes during your bedtime
+#[poise::command(slash_command, guild_only, rename = "partner")]
+pub async fn bedtime_partner<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "Your partner, leave empty to remove them"] partner: Option<UserId>,
+) -> Result<()> {
+    let user_id = ctx.author().id;
+    ensure!(partner != Some(user_id), "You can't be your own partner");
+
+    ctx.data()
+        .with_mut(|cfg| {
+            // otherwise removing the partner would be a way around their approval
+            if let Some(current) = required_approval(cfg, user_id, Utc::now()) {
+                bail!("You can't change your partner during your bedtime, ask {} to approve", current.mention());
+            }
+            match partner {
+                Some(partner) => cfg.partners.insert(user_id, partner),
+                None => cfg.partners.remove(&user_id),
+            };
+            Ok(())
+        })
+        .await?;
+
+    match partner {
+        Some(partner) => ctx.say(format!("🤝 {} is now your bedtime partner", partner.mention())).await?,
+        None => ctx.say("🤝 You no longer have a bedtime partner").await?,
+    };
+
+    Ok(())
+}
+
+/// View streaks and how often bedtimes were kept
+#[poise::command(slash_command, guild_only, rename = "stats")]
+pub async fn bedtime_stats<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "Member, defaults to you"] user: Option<UserId>,
+) -> Result<()> {
+    let user_id = user.unwrap_or(ctx.author().id);
+    let nights = ctx.data().with_ok(|cfg| cfg.history.get(&user_id).cloned().unwrap_or_default()).await?;
+    ensure!(!nights.is_empty(), "No bedtimes of {} have been recorded yet", user_id.mention());
+
+    ctx.send(CreateReply::new().embed(stats_embed(user_id, &nights))).await?;
+
+    Ok(())
+}
+
+/// Export your bedtimes to your calendar
+#[poise::command(slash_command, guild_only, rename = "export")]
+pub async fn bedtime_export<D: With<ConfigT>>(ctx: CmdContext<'_, D>) -> Result<()> {
+    let user_id = ctx.author().id;
+    let ics = ctx
+        .data()
+        .with_ok(|cfg| {
+            let bedtimes =
+                cfg.bedtimes.iter().filter(|(_, b)| b.user == user_id).map(|(id, b)| (*id, b.clone())).collect();
+            ical::export(&bedtimes, cfg.timezone(user_id), cfg.duration, Utc::now())
+        })
+        .await?;
+
+    ctx.send(CreateReply::new().attachment(CreateAttachment::bytes(ics.into_bytes(), "bedtimes.ics")).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+/// Import recurring events from your calendar as bedtimes
+#[poise::command(slash_command, guild_only, rename = "import")]
+pub async fn bedtime_import<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "An .ics file"] calendar: Attachment,
+) -> Result<()> {
+    let user_id = ctx.author().id;
+    let (tz, existing) = ctx
+        .data()
+        .with_ok(|cfg| {
+            let existing = cfg.bedtimes.iter().filter(|(_, b)| b.user == user_id).map(|(id, _)| *id).collect();
+            (cfg.timezone(user_id), existing)
+        })
+        .await?;
+    let bytes = calendar.download().await?;
+    let (bedtimes, skipped) = ical::import(&String::from_utf8_lossy(&bytes), user_id, tz, &existing)?;
+    ensure!(!bedtimes.is_empty(), "None of the events could be imported:\n{}", skipped.join("\n"));
+
+    let count = bedtimes.len();
+    ctx.data()
+        .with_mut_ok(|cfg| {
+            for (id, bedtime) in bedtimes {
+                // the ID may belong to someone else's bedtime if they shared their export
+                let id = if cfg.bedtimes.contains_key(&id) { Uuid::new_v4() } else { id };
+                cfg.bedtimes.insert(id, bedtime);
+            }
+        })
+        .await?;
+
+    let mut content = format!("📅 Imported {count} bedtimes, see them with /bedtimes");
+    if !skipped.is_empty() {
+        content += &format!("\nSkipped:\n{}", skipped.join("\n"));
+    }
+    ctx.say(content).await?;
+
+    Ok(())
+}
+
+/// View everyone's bedtimes (moderators only)
+#[poise::command(slash_command, guild_only, rename = "list")]
+pub async fn bedtime_list<D: With<ConfigT>>(ctx: CmdContext<'_, D>) -> Result<()> {
+    ensure!(is_moderator(ctx.author_member().await.as_deref()), "Only moderators can see everyone's bedtimes");
+
+    let now = Utc::now();
+    let bedtimes = ctx
+        .data()
+        .with_ok(|cfg| {
+            cfg.bedtimes
+                .iter()
+                .map(|(&id, bedtime)| {
+                    let tz = cfg.timezone(bedtime.user);
+                    let next = bedtime.next(now, tz);
+                    let end = bedtime.end_of(next, tz, cfg.duration).with_timezone(&tz);
+                    (id, bedtime.clone(), format!("{} until {}", format_datetime(next, now, tz), end.format("%H:%M")))
+                })
+                .sorted_by_key(|(_, bedtime, _)| bedtime.user)
+                .collect_vec()
+        })
+        .await?;
+    ensure!(!bedtimes.is_empty(), "Nobody has a bedtime");
+
+    let lines = bedtimes
+        .iter()
+        .map(|(_, bedtime, when)| {
+            let repeats = bedtime.repeat.iter().map(|wd| wd.0.to_string()).join(", ");
+            format!(
+                "{} – {when}{}{}",
+                bedtime.user.mention(),
+                if repeats.is_empty() { String::new() } else { format!(", repeats on {repeats}") },
+                if bedtime.locked { " 🔒" } else { "" }
+            )
+        })
+        .collect_vec();
+    let mut description = String::new();
+    for line in &lines {
+        if description.len() + line.len() > 4000 {
+            description += "…";
+            break;
+        }
+        description += line;
+        description += "\n";
+    }
+
+    let options = bedtimes
+        .iter()
+        .take(25)
+        .map(|(id, bedtime, when)| {
+            let name = safe_name(ctx.serenity_context(), bedtime.user);
+            CreateSelectMenuOption::new(format!("{name} – {when}"), id.to_string())
+        })
+        .collect();
+    let select = CreateSelectMenu::new(SELECT_BEDTIME_ID, CreateSelectMenuKind::String { options })
+        .placeholder("Manage a bedtime...");
+
+    ctx.send(
+        CreateReply::new()
+            .embed(CreateEmbed::new().title("🌙 Bedtimes").description(description).color(Color::DARK_PURPLE))
+            .components(vec![CreateActionRow::SelectMenu(select)])
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
 /// View your bedtimes
 #[poise::command(slash_command, guild_only)]
 pub async fn bedtimes<D: With<ConfigT>>(ctx: CmdContext<'_, D>) -> Result<()> {
@@ -51,7 +321,7 @@ pub async fn bedtimes<D: With<ConfigT>>(ctx: CmdContext<'_, D>) -> Result<()> {
             cfg.bedtimes
                 .iter()
                 .filter(|(_, bedtime)| bedtime.user == ctx.author().id)
-                .min_by_key(|(_, bedtime)| bedtime.next(now))
+                .min_by_key(|(_, bedtime)| bedtime.next(now, cfg.timezone(bedtime.user)))
                 .map(|(id, bedtime)| (*id, bedtime.clone()))
         })
         .await?
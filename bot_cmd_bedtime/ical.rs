@@ -0,0 +1,341 @@
+//! Just enough iCalendar (RFC 5545) to exchange bedtimes with calendar apps
+
+use crate::bedtime::{Bedtime, BedtimeEnd};
+use bot_core::time::iso_weekday::IsoWeekday;
+use bot_core::time::resolve_local;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Offset as _, TimeDelta, TimeZone as _, Utc, Weekday};
+use chrono_tz::Tz;
+use eyre::{OptionExt as _, Result, bail, ensure, eyre};
+use poise::serenity_prelude::UserId;
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+const WEEKDAYS: [(Weekday, &str); 7] = [
+    (Weekday::Mon, "MO"),
+    (Weekday::Tue, "TU"),
+    (Weekday::Wed, "WE"),
+    (Weekday::Thu, "TH"),
+    (Weekday::Fri, "FR"),
+    (Weekday::Sat, "SA"),
+    (Weekday::Sun, "SU"),
+];
+
+pub(crate) fn export(
+    bedtimes: &BTreeMap<Uuid, Bedtime>,
+    tz: Tz,
+    default_duration: TimeDelta,
+    now: DateTime<Utc>,
+) -> String {
+    let local = |dt: DateTime<Utc>| dt.with_timezone(&tz).format("%Y%m%dT%H%M%S");
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//discord-bot-rs//bedtime//EN".to_string(),
+    ];
+    let earliest = bedtimes.values().map(|bedtime| bedtime.first).min().unwrap_or(now);
+    lines.extend(vtimezone(tz, earliest.with_timezone(&tz).year()));
+    for (id, bedtime) in bedtimes {
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{id}@bedtime"),
+            format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")),
+            format!("DTSTART;TZID={}:{}", tz.name(), local(bedtime.first)),
+        ]);
+        // wake-up times are an end, durations stay durations so that importing gives back the same bedtime
+        let end = bedtime.end_of(bedtime.first, tz, default_duration);
+        lines.push(match bedtime.end {
+            Some(BedtimeEnd::WakeUp(_)) => format!("DTEND;TZID={}:{}", tz.name(), local(end)),
+            _ => {
+                let duration = end - bedtime.first;
+                format!("DURATION:PT{}H{}M", duration.num_hours(), duration.num_minutes() % 60)
+            }
+        });
+        if !bedtime.repeat.is_empty() {
+            let days = WEEKDAYS
+                .iter()
+                .filter(|(weekday, _)| bedtime.repeat.contains(&IsoWeekday(*weekday)))
+                .map(|(_, day)| *day)
+                .collect::<Vec<_>>();
+            lines.push(format!("RRULE:FREQ=WEEKLY;BYDAY={}", days.join(",")));
+        }
+        lines.extend(["SUMMARY:🌙 Bedtime".to_string(), "END:VEVENT".to_string()]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.into_iter().map(|line| line + "\r\n").collect()
+}
+
+/// Defines `tz` for the `TZID`s, with the daylight saving time rules it has in `year`
+fn vtimezone(tz: Tz, year: i32) -> Vec<String> {
+    let offset_at = |time: DateTime<Utc>| tz.offset_from_utc_datetime(&time.naive_utc()).fix().local_minus_utc();
+    let start = NaiveDate::from_yo_opt(year, 1).unwrap_or_default().and_time(Default::default()).and_utc();
+
+    let mut transitions = vec![];
+    let mut time = start;
+    while time.year() == year {
+        let next = time + TimeDelta::hours(1);
+        if offset_at(time) != offset_at(next) {
+            let at = (1..=60).map(|m| time + TimeDelta::minutes(m)).find(|&t| offset_at(t) != offset_at(time));
+            let at = at.unwrap_or(next);
+            transitions.push((at, offset_at(time), offset_at(at)));
+        }
+        time = next;
+    }
+
+    let mut lines = vec!["BEGIN:VTIMEZONE".to_string(), format!("TZID:{}", tz.name())];
+    if transitions.is_empty() {
+        let offset = fmt_offset(offset_at(start));
+        lines.extend([
+            "BEGIN:STANDARD".to_string(),
+            format!("DTSTART:{}", start.format("%Y%m%dT%H%M%S")),
+            format!("TZOFFSETFROM:{offset}"),
+            format!("TZOFFSETTO:{offset}"),
+            "END:STANDARD".to_string(),
+        ]);
+    }
+    for (at, from, to) in transitions {
+        // the transition happens at this local time, before the offset changes
+        let local = at.naive_utc() + TimeDelta::seconds(from.into());
+        let date = local.date();
+        let nth = if (date + TimeDelta::days(7)).month() != date.month() { -1 } else { date.day0() as i32 / 7 + 1 };
+        let day = WEEKDAYS.iter().find(|(weekday, _)| *weekday == date.weekday()).map_or("SU", |(_, day)| *day);
+        let kind = if to > from { "DAYLIGHT" } else { "STANDARD" };
+        lines.extend([
+            format!("BEGIN:{kind}"),
+            format!("DTSTART:{}", local.format("%Y%m%dT%H%M%S")),
+            format!("TZOFFSETFROM:{}", fmt_offset(from)),
+            format!("TZOFFSETTO:{}", fmt_offset(to)),
+            format!("RRULE:FREQ=YEARLY;BYMONTH={};BYDAY={nth}{day}", date.month()),
+            format!("END:{kind}"),
+        ]);
+    }
+    lines.push("END:VTIMEZONE".to_string());
+    lines
+}
+
+/// Formats a UTC offset in seconds like `+0130`
+fn fmt_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.unsigned_abs();
+    format!("{sign}{:02}{:02}", seconds / 3600, seconds % 3600 / 60)
+}
+
+/// Parses the events of a calendar into bedtimes of the user, `tz` is used for times without a timezone.
+/// Exported bedtimes keep their ID, the ones in `existing` are skipped so that importing twice doesn't duplicate them.
+/// Returns the bedtimes and a note for each event that couldn't be imported.
+pub(crate) fn import(
+    input: &str,
+    user: UserId,
+    tz: Tz,
+    existing: &BTreeSet<Uuid>,
+) -> Result<(Vec<(Uuid, Bedtime)>, Vec<String>)> {
+    // long lines are folded by starting the next line with whitespace
+    let unfolded = input.replace("\r\n", "\n").replace("\n ", "").replace("\n\t", "");
+
+    let mut events = vec![];
+    let mut current: Option<Vec<&str>> = None;
+    for line in unfolded.lines() {
+        if line == "BEGIN:VEVENT" {
+            current = Some(vec![]);
+        } else if line == "END:VEVENT" {
+            events.extend(current.take());
+        } else if let Some(event) = &mut current {
+            event.push(line);
+        }
+    }
+    ensure!(!events.is_empty(), "The file doesn't contain any events");
+
+    let mut bedtimes = vec![];
+    let mut skipped = vec![];
+    for event in events {
+        let summary = property(&event, "SUMMARY").map_or("Untitled event", |(_, value)| value);
+        let id = property(&event, "UID")
+            .and_then(|(_, uid)| Uuid::try_parse(uid.strip_suffix("@bedtime")?).ok())
+            .unwrap_or_else(Uuid::new_v4);
+        if existing.contains(&id) {
+            skipped.push(format!("{summary}: This bedtime already exists"));
+            continue;
+        }
+        match event_to_bedtime(&event, user, tz) {
+            Ok(bedtime) => bedtimes.push((id, bedtime)),
+            Err(error) => skipped.push(format!("{summary}: {error}")),
+        }
+    }
+    Ok((bedtimes, skipped))
+}
+
+fn event_to_bedtime(event: &[&str], user: UserId, tz: Tz) -> Result<Bedtime> {
+    let (params, value) = property(event, "DTSTART").ok_or_eyre("No start time")?;
+    let start = parse_datetime(params, value, tz)?;
+    let first = start.to_utc();
+
+    let (duration, end) = match (property(event, "DTEND"), property(event, "DURATION")) {
+        (Some((params, value)), _) => {
+            let end = parse_datetime(params, value, tz)?;
+            (Some(end.to_utc() - first), Some(BedtimeEnd::WakeUp(end.with_timezone(&tz).time())))
+        }
+        (None, Some((_, value))) => {
+            let duration = parse_duration(value)?;
+            (Some(duration), Some(BedtimeEnd::Duration(duration)))
+        }
+        (None, None) => (None, None),
+    };
+    if let Some(duration) = duration {
+        ensure!(
+            duration > TimeDelta::zero() && duration <= TimeDelta::days(1),
+            "A bedtime has to last between 0 and 24 hours"
+        );
+    }
+
+    // the rule's weekdays are in the event's timezone, but bedtimes repeat on the weekdays of the member's
+    let shift = (first.with_timezone(&tz).date_naive() - start.date_naive()).num_days();
+    let repeat = match property(event, "RRULE") {
+        Some((_, rule)) => parse_rule(rule, start.date_naive())?
+            .into_iter()
+            .map(|IsoWeekday(weekday)| match shift {
+                1 => IsoWeekday(weekday.succ()),
+                -1 => IsoWeekday(weekday.pred()),
+                _ => IsoWeekday(weekday),
+            })
+            .collect(),
+        None => BTreeSet::new(),
+    };
+
+    Ok(Bedtime { user, first, repeat, end, restrictions: Default::default(), set_by: None, locked: false })
+}
+
+/// The parameters and value of a property, e.g. `DTSTART;TZID=Europe/Berlin:20250101T230000`
+fn property<'a>(event: &[&'a str], name: &str) -> Option<(&'a str, &'a str)> {
+    event.iter().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        let (key_name, params) = key.split_once(';').unwrap_or((key, ""));
+        key_name.eq_ignore_ascii_case(name).then_some((params, value))
+    })
+}
+
+/// Parses a date and time, in the timezone of its `TZID` or `tz` if it has none
+fn parse_datetime(params: &str, value: &str, tz: Tz) -> Result<DateTime<Tz>> {
+    ensure!(!params.contains("VALUE=DATE") || params.contains("VALUE=DATE-TIME"), "All-day events aren't supported");
+    if let Some(utc) = value.strip_suffix('Z') {
+        return Ok(NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")?.and_utc().with_timezone(&Tz::UTC));
+    }
+    let tz = match params.split(';').find_map(|param| param.strip_prefix("TZID=")) {
+        Some(tzid) => tzid.trim_matches('"').parse().map_err(|_| eyre!("Unknown timezone {tzid}"))?,
+        None => tz,
+    };
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")?;
+    resolve_local(&tz, naive).ok_or_eyre("Invalid time")
+}
+
+/// Parses durations like `PT8H30M`
+fn parse_duration(value: &str) -> Result<TimeDelta> {
+    let time = value.strip_prefix("PT").ok_or_eyre("Only durations in hours and minutes are supported")?;
+    let mut duration = TimeDelta::zero();
+    let mut number = String::new();
+    for c in time.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'H' | 'M' | 'S' => {
+                let n: i64 = std::mem::take(&mut number).parse()?;
+                duration += match c {
+                    'H' => TimeDelta::hours(n),
+                    'M' => TimeDelta::minutes(n),
+                    _ => TimeDelta::seconds(n),
+                };
+            }
+            _ => bail!("Invalid duration {value}"),
+        }
+    }
+    Ok(duration)
+}
+
+/// Bedtimes only repeat on weekdays, so only weekly and daily rules without an interval can be imported
+fn parse_rule(rule: &str, first: NaiveDate) -> Result<BTreeSet<IsoWeekday>> {
+    let parts: BTreeMap<&str, &str> = rule.split(';').filter_map(|part| part.split_once('=')).collect();
+    ensure!(parts.get("INTERVAL").is_none_or(|&i| i == "1"), "Repeating less than every week isn't supported");
+    ensure!(!parts.contains_key("COUNT") && !parts.contains_key("UNTIL"), "Repeats that end aren't supported");
+
+    let weekdays: Vec<Weekday> = match (parts.get("FREQ").copied(), parts.get("BYDAY")) {
+        (Some("DAILY"), None) => WEEKDAYS.iter().map(|(weekday, _)| *weekday).collect(),
+        (Some("WEEKLY"), None) => vec![first.weekday()],
+        (Some("WEEKLY"), Some(days)) => days
+            .split(',')
+            .map(|day| {
+                WEEKDAYS
+                    .iter()
+                    .find(|(_, name)| *name == day)
+                    .map(|(weekday, _)| *weekday)
+                    .ok_or_eyre("Invalid weekday")
+            })
+            .collect::<Result<_>>()?,
+        _ => bail!("Only daily and weekly repeats are supported"),
+    };
+    Ok(weekdays.into_iter().map(IsoWeekday).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{NaiveTime, TimeZone};
+
+    #[test]
+    fn export_and_import() {
+        let tz = chrono_tz::Europe::Berlin;
+        let bedtime = Bedtime {
+            user: UserId::new(1),
+            first: tz.with_ymd_and_hms(2025, 1, 6, 23, 0, 0).unwrap().to_utc(),
+            repeat: [Weekday::Mon, Weekday::Fri].into_iter().map(IsoWeekday).collect(),
+            end: Some(BedtimeEnd::Duration(TimeDelta::hours(8))),
+            restrictions: Default::default(),
+            set_by: None,
+            locked: false,
+        };
+        let wake_up =
+            Bedtime { end: Some(BedtimeEnd::WakeUp(NaiveTime::from_hms_opt(7, 0, 0).unwrap())), ..bedtime.clone() };
+        let bedtimes = BTreeMap::from([(Uuid::nil(), bedtime.clone()), (Uuid::from_u128(1), wake_up.clone())]);
+        let ics = export(&bedtimes, tz, TimeDelta::hours(6), Utc::now());
+        assert!(ics.contains("DTSTART;TZID=Europe/Berlin:20250106T230000\r\n"));
+        assert!(ics.contains("DURATION:PT8H0M\r\n"));
+        assert!(ics.contains("DTEND;TZID=Europe/Berlin:20250107T070000\r\n"));
+        assert!(ics.contains("RRULE:FREQ=WEEKLY;BYDAY=MO,FR\r\n"));
+        assert!(ics.contains(
+            "BEGIN:DAYLIGHT\r\nDTSTART:20250330T020000\r\nTZOFFSETFROM:+0100\r\nTZOFFSETTO:+0200\r\n\
+             RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r\nEND:DAYLIGHT\r\n"
+        ));
+
+        let (bedtimes, skipped) = import(&ics, UserId::new(1), tz, &BTreeSet::new()).unwrap();
+        assert_eq!(bedtimes, vec![(Uuid::nil(), bedtime), (Uuid::from_u128(1), wake_up)]);
+        assert!(skipped.is_empty());
+
+        let (bedtimes, skipped) = import(&ics, UserId::new(1), tz, &BTreeSet::from([Uuid::nil()])).unwrap();
+        assert_eq!(bedtimes.len(), 1);
+        assert_eq!(skipped, vec!["🌙 Bedtime: This bedtime already exists".to_string()]);
+    }
+
+    #[test]
+    fn import_weekdays_of_another_timezone() {
+        // Monday evening in Los Angeles is Tuesday morning in Berlin
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART;TZID=America/Los_Angeles:20250106T220000\r\n\
+                   DTEND;TZID=America/Los_Angeles:20250107T060000\r\nRRULE:FREQ=WEEKLY;BYDAY=MO,SU\r\n\
+                   END:VEVENT\r\nEND:VCALENDAR\r\n";
+        let (bedtimes, _) = import(ics, UserId::new(1), chrono_tz::Europe::Berlin, &BTreeSet::new()).unwrap();
+        let bedtime = &bedtimes[0].1;
+        assert_eq!(bedtime.repeat, [Weekday::Tue, Weekday::Mon].into_iter().map(IsoWeekday).collect());
+        assert_eq!(bedtime.end, Some(BedtimeEnd::WakeUp(NaiveTime::from_hms_opt(15, 0, 0).unwrap())));
+    }
+
+    #[test]
+    fn import_calendar_app_export() {
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART:20250107T220000Z\r\nDURATION:PT7H30M\r\n\
+                   RRULE:FREQ=DAILY\r\nSUMMARY:Sle\r\n ep\r\nEND:VEVENT\r\nBEGIN:VEVENT\r\n\
+                   DTSTART;VALUE=DATE:20250107\r\nSUMMARY:Holiday\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let (bedtimes, skipped) = import(ics, UserId::new(1), Tz::UTC, &BTreeSet::new()).unwrap();
+        assert_eq!(bedtimes.len(), 1);
+        let bedtime = &bedtimes[0].1;
+        assert_eq!(bedtime.first, Utc.with_ymd_and_hms(2025, 1, 7, 22, 0, 0).unwrap());
+        assert_eq!(bedtime.repeat.len(), 7);
+        assert_eq!(bedtime.end, Some(BedtimeEnd::Duration(TimeDelta::minutes(450))));
+        assert_eq!(skipped, vec!["Holiday: All-day events aren't supported".to_string()]);
+    }
+}
@@ -1,35 +1,140 @@
+mod approval;
 mod bedtime;
 mod buttons;
 mod cmd;
+mod ical;
 mod r#loop;
+mod restrictions;
+mod snooze;
+mod stats;
+mod warnings;
 
 use crate::bedtime::Bedtime;
 pub use crate::buttons::*;
 pub use crate::cmd::*;
 use crate::r#loop::bedtime_loop;
+pub use crate::r#loop::on_voice_update;
+use crate::restrictions::Restriction;
+pub use crate::restrictions::{StateT, on_presence_update};
+pub use crate::snooze::btn_snooze;
+use bot_core::interval_set::IntervalSet;
 use bot_core::serde::LiteralRegex;
+use bot_core::time::iso_week::IsoWeekSerde;
+use bot_core::time::local_timezone;
 use bot_core::{State, With};
-use chrono::TimeDelta;
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use chrono_tz::Tz;
 use eyre::Result;
-use poise::serenity_prelude::{Context, GuildId, RoleId};
-use std::collections::BTreeMap;
+use itertools::Itertools as _;
+use poise::serenity_prelude::{ChannelId, Context, GuildId, Member, RoleId, UserId};
+use std::collections::{BTreeMap, BTreeSet};
 use uuid::Uuid;
 
 pub const TOGGLE_WEEKDAY_BUTTON_ID: &str = "bedtime.weekday";
 pub const DELETE_BUTTON_ID: &str = "bedtime.delete";
 pub const SELECT_BEDTIME_ID: &str = "bedtime.select";
+pub const SNOOZE_BUTTON_ID: &str = "bedtime.snooze";
+pub const END_BUTTON_ID: &str = "bedtime.end";
+pub const RESTRICTION_BUTTON_ID: &str = "bedtime.restriction";
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, sensible::Default)]
 pub struct ConfigT {
+    /// Default duration of bedtimes without their own end
     #[serde(with = "bot_core::serde::td_seconds")]
     #[default(TimeDelta::hours(6))]
     duration: TimeDelta,
     ignored_vc_description: Option<LiteralRegex>,
     role: Option<RoleId>,
+    /// Also deny members the Connect permission in voice channels during their bedtime
+    #[serde(default)]
+    block_connect: bool,
     bedtimes: BTreeMap<Uuid, Bedtime>,
+    #[serde(default)]
+    timezones: BTreeMap<UserId, Tz>,
+    #[serde(default = "warnings::default_warnings")]
+    #[default(warnings::default_warnings())]
+    warnings: Vec<warnings::Warning>,
+    #[serde(default)]
+    snooze: snooze::SnoozeConfig,
+    #[serde(default)]
+    snoozes: BTreeMap<UserId, snooze::Snooze>,
+    /// Accountability partners who have to approve snoozes and changes during active bedtimes
+    #[serde(default)]
+    partners: BTreeMap<UserId, UserId>,
+    /// What happened during each user's bedtimes, by the date of the evening
+    #[serde(default)]
+    history: BTreeMap<UserId, BTreeMap<NaiveDate, stats::Night>>,
+    /// Channel for the weekly bedtime report
+    report_channel: Option<ChannelId>,
+    last_report: Option<IsoWeekSerde>,
+    #[serde(default)]
+    nudge: restrictions::NudgeConfig,
+    /// Timeouts given for bedtimes, so that only those are lifted early
+    #[serde(default)]
+    timeouts: BTreeMap<UserId, DateTime<Utc>>,
+    /// Voice channels members were blocked from during their bedtime, so that only those overwrites are removed
+    #[serde(default)]
+    blocks: BTreeMap<UserId, BTreeSet<ChannelId>>,
 }
 
-pub async fn setup(ctx: Context, data: impl With<ConfigT> + State<GuildId>) -> Result<()> {
+impl ConfigT {
+    /// The timezone a user's bedtimes are in, defaults to the server's timezone
+    fn timezone(&self, user_id: UserId) -> Tz {
+        self.timezones.get(&user_id).copied().unwrap_or_else(local_timezone)
+    }
+
+    /// The times each user has bedtime around `now`, excluding snoozed times
+    fn intervals(&self, now: DateTime<Utc>) -> BTreeMap<UserId, IntervalSet<DateTime<Utc>>> {
+        self.bedtimes
+            .values()
+            .into_group_map_by(|bedtime| bedtime.user)
+            .into_iter()
+            .map(|(user_id, bedtimes)| {
+                let mut intervals = bedtimes
+                    .into_iter()
+                    .flat_map(|bedtime| {
+                        let tz = self.timezone(bedtime.user);
+                        let starts = bedtime.currently_relevant_bedtimes(now, tz);
+                        starts.into_iter().map(move |start| start..bedtime.end_of(start, tz, self.duration))
+                    })
+                    .collect::<IntervalSet<_>>();
+                if let Some(snooze) = self.snoozes.get(&user_id) {
+                    intervals.remove(snooze.start, snooze.until);
+                }
+                (user_id, intervals)
+            })
+            .collect()
+    }
+
+    /// The restrictions of the user's bedtimes that are active at `now`
+    fn restrictions(&self, user_id: UserId, now: DateTime<Utc>) -> BTreeSet<Restriction> {
+        if self.snoozes.get(&user_id).is_some_and(|snooze| snooze.start <= now && now < snooze.until) {
+            return BTreeSet::new();
+        }
+        let tz = self.timezone(user_id);
+        self.bedtimes
+            .values()
+            .filter(|bedtime| bedtime.user == user_id)
+            .filter(|bedtime| {
+                bedtime
+                    .currently_relevant_bedtimes(now, tz)
+                    .into_iter()
+                    .any(|start| start <= now && now < bedtime.end_of(start, tz, self.duration))
+            })
+            .flat_map(|bedtime| bedtime.restrictions.iter().copied())
+            .collect()
+    }
+}
+
+/// Moderators can manage everyone's bedtimes
+fn is_moderator(member: Option<&Member>) -> bool {
+    member.and_then(|m| m.permissions).is_some_and(|p| p.moderate_members() || p.manage_guild())
+}
+
+pub async fn setup(
+    ctx: Context,
+    data: impl With<ConfigT> + State<GuildId> + State<bot_core::audio::StateT> + State<StateT>,
+) -> Result<()> {
     tokio::spawn(bedtime_loop(ctx, data));
     Ok(())
 }
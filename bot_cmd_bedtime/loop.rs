@@ -1,14 +1,22 @@
-use crate::ConfigT;
+use crate::restrictions::{Restriction, lift_timeout, nudge, timeout};
+use crate::stats::{night_of, record_nights, record_rejoin, weekly_report};
+use crate::warnings::warn_bedtime;
+use crate::{ConfigT, StateT};
 use bot_core::ext::option::OptionExt as _;
-use bot_core::interval_set::IntervalSet;
-use bot_core::{State, With, get_member};
-use chrono::{DateTime, Utc};
+use bot_core::voice_change::VoiceChange;
+use bot_core::{EvtContext, State, With, get_member};
+use chrono::{DateTime, TimeDelta, Utc};
 use eyre::Result;
-use itertools::Itertools;
-use poise::serenity_prelude::{Context, GuildId, Member, UserId};
+use poise::serenity_prelude::{
+    ChannelId, ChannelType, Context, GuildChannel, GuildId, Member, PermissionOverwrite, PermissionOverwriteType,
+    Permissions, UserId, VoiceState,
+};
 use std::collections::{BTreeMap, HashSet};
 
-pub(crate) async fn bedtime_loop(ctx: Context, data: impl With<ConfigT> + State<GuildId>) {
+pub(crate) async fn bedtime_loop(
+    ctx: Context,
+    data: impl With<ConfigT> + State<GuildId> + State<bot_core::audio::StateT> + State<StateT>,
+) {
     let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
     interval.tick().await;
     loop {
@@ -19,62 +27,109 @@ pub(crate) async fn bedtime_loop(ctx: Context, data: impl With<ConfigT> + State<
     }
 }
 
-async fn enforce_and_lift_bedtimes(ctx: &Context, data: &(impl With<ConfigT> + State<GuildId>)) -> Result<()> {
+async fn enforce_and_lift_bedtimes(
+    ctx: &Context,
+    data: &(impl With<ConfigT> + State<GuildId> + State<bot_core::audio::StateT> + State<StateT>),
+) -> Result<()> {
     let guild_id: GuildId = *data.state();
 
     let now = Utc::now();
-    let intervals_by_user = get_bedtime_intervals(data, now).await?;
     prune_outdated_bedtimes(data, now).await?;
     let cfg = data.with_ok(|cfg| cfg.clone()).await?;
+    let intervals_by_user = cfg.intervals(now);
 
+    let mut nights = vec![];
+    let mut timeouts = cfg.timeouts.clone();
     for (&user_id, intervals) in intervals_by_user.iter() {
         let Some(member) = get_member(ctx, guild_id, user_id) else { continue };
-        if intervals.find(now).is_some() {
-            enforce_bedtime(ctx, &cfg, member).await?;
+        if let Some(interval) = intervals.find(now) {
+            restrict(ctx, data, &cfg, &member, now, interval.end, &mut timeouts).await;
+            let disconnected = enforce_bedtime(ctx, data, &cfg, member).await?;
+            nights.push((user_id, night_of(interval.start, cfg.timezone(user_id)), disconnected));
         } else {
-            lift_bedtime(ctx, &cfg, member).await?;
+            lift_bedtime(ctx, data, &cfg, &member).await?;
+            lift_bedtime_timeout(ctx, &member, now, &mut timeouts).await?;
+            if let Some(start) = intervals.next_start(now)
+                && let Some(channel) = enforced_voice_channel(ctx, &cfg, &member)?
+                && let Err(error) = warn_bedtime(ctx, data, &cfg, &member, &channel, start - now).await
+            {
+                tracing::warn!("Failed to warn {} about their bedtime: {error:?}", member.display_name());
+            }
         }
     }
+    record_nights(data, &cfg, nights).await?;
+
+    // members whose bedtimes were all deleted while they were restricted
+    let orphaned = cfg
+        .blocks
+        .keys()
+        .chain(timeouts.keys())
+        .copied()
+        .filter(|user_id| !intervals_by_user.contains_key(user_id))
+        .collect::<HashSet<_>>();
+    for user_id in orphaned {
+        let Some(member) = get_member(ctx, guild_id, user_id) else { continue };
+        lift_bedtime(ctx, data, &cfg, &member).await?;
+        lift_bedtime_timeout(ctx, &member, now, &mut timeouts).await?;
+    }
+
+    timeouts.retain(|_, until| *until > now);
+    if timeouts != cfg.timeouts {
+        data.with_mut_ok(|cfg| cfg.timeouts = timeouts).await?;
+    }
+
+    if let Err(error) = weekly_report(ctx, data, &cfg).await {
+        tracing::warn!("Failed to post the weekly bedtime report: {error:?}");
+    }
 
     Ok(())
 }
 
-async fn get_bedtime_intervals(
-    data: &impl With<ConfigT>,
-    now: DateTime<Utc>,
-) -> Result<BTreeMap<UserId, IntervalSet<DateTime<Utc>>>> {
-    data.with_ok(|cfg| {
-        cfg.bedtimes
-            .values()
-            .chunk_by(|bedtime| bedtime.user)
-            .into_iter()
-            .map(|(user_id, bedtimes)| {
-                let intervals = bedtimes
-                    .into_iter()
-                    .flat_map(|x| x.currently_relevant_bedtimes(now))
-                    .map(|x| x..(x + cfg.duration))
-                    .collect::<IntervalSet<_>>();
-                (user_id, intervals)
-            })
-            .collect::<BTreeMap<_, _>>()
-    })
-    .await
+/// Disconnect members who join voice during their bedtime right away instead of on the next tick
+pub async fn on_voice_update(
+    ctx: EvtContext<'_, impl With<ConfigT>>,
+    guild_id: GuildId,
+    (old, new): (&Option<VoiceState>, &VoiceState),
+) -> Result<()> {
+    let (VoiceChange::Join { .. } | VoiceChange::Move { .. }) = VoiceChange::new((old, new)) else { return Ok(()) };
+    let user_id = new.user_id;
+
+    let now = Utc::now();
+    let interval = ctx.user_data.with_ok(|cfg| cfg.intervals(now).remove(&user_id)?.find(now)).await?;
+    let Some(interval) = interval else { return Ok(()) };
+    let Some(member) = get_member(ctx.serenity_context, guild_id, user_id) else { return Ok(()) };
+
+    // only members who are having their bedtime get this far
+    let cfg = ctx.user_data.with_ok(|cfg| cfg.clone()).await?;
+    if enforce_bedtime(ctx.serenity_context, ctx.user_data, &cfg, member).await? {
+        record_rejoin(ctx.user_data, user_id, night_of(interval.start, cfg.timezone(user_id))).await?;
+    }
+    Ok(())
 }
 
 async fn prune_outdated_bedtimes(data: &impl With<ConfigT>, now: DateTime<Utc>) -> Result<()> {
-    let outdated = data
+    let (outdated, outdated_snoozes) = data
         .with_ok(|cfg| {
-            cfg.bedtimes
+            let bedtimes = cfg
+                .bedtimes
                 .iter()
-                .filter(|(_, b)| b.repeat.is_empty() && b.first < now - cfg.duration)
+                .filter(|(_, b)| b.repeat.is_empty() && b.end_of(b.first, cfg.timezone(b.user), cfg.duration) < now)
                 .map(|(id, _)| *id)
-                .collect::<HashSet<_>>()
+                .collect::<HashSet<_>>();
+            let snoozes = cfg
+                .snoozes
+                .iter()
+                .filter(|(_, s)| s.until < now - TimeDelta::days(1))
+                .map(|(user_id, _)| *user_id)
+                .collect::<HashSet<_>>();
+            (bedtimes, snoozes)
         })
         .await?;
 
-    if !outdated.is_empty() {
+    if !outdated.is_empty() || !outdated_snoozes.is_empty() {
         data.with_mut_ok(|cfg| {
             cfg.bedtimes.retain(|id, _| !outdated.contains(id));
+            cfg.snoozes.retain(|user_id, _| !outdated_snoozes.contains(user_id));
         })
         .await?;
     }
@@ -82,7 +137,56 @@ async fn prune_outdated_bedtimes(data: &impl With<ConfigT>, now: DateTime<Utc>)
     Ok(())
 }
 
-async fn enforce_bedtime(ctx: &Context, cfg: &ConfigT, member: Member) -> Result<()> {
+/// Apply the opt-in restrictions of the member's active bedtime, which ends at `until`
+async fn restrict(
+    ctx: &Context,
+    data: &impl State<StateT>,
+    cfg: &ConfigT,
+    member: &Member,
+    now: DateTime<Utc>,
+    until: DateTime<Utc>,
+    timeouts: &mut BTreeMap<UserId, DateTime<Utc>>,
+) {
+    let user_id = member.user.id;
+    let restrictions = cfg.restrictions(user_id, now);
+
+    if restrictions.contains(&Restriction::Timeout) && timeouts.get(&user_id) != Some(&until) {
+        // remembered even if it fails, so that it isn't retried every minute
+        timeouts.insert(user_id, until);
+        if let Err(error) = timeout(ctx, member.clone(), until).await {
+            tracing::warn!("Failed to time out {}: {error:?}", member.display_name());
+        }
+    }
+
+    if restrictions.contains(&Restriction::GameNudge) {
+        let activities = ctx
+            .cache
+            .guild(member.guild_id)
+            .and_then(|guild| guild.presences.get(&user_id).map(|presence| presence.activities.clone()))
+            .unwrap_or_default();
+        if let Err(error) = nudge(ctx, data, &cfg.nudge, user_id, &activities).await {
+            tracing::warn!("Failed to nudge {}: {error:?}", member.display_name());
+        }
+    }
+}
+
+/// Lift the timeout of a bedtime that ended early, e.g. because it was snoozed or deleted
+async fn lift_bedtime_timeout(
+    ctx: &Context,
+    member: &Member,
+    now: DateTime<Utc>,
+    timeouts: &mut BTreeMap<UserId, DateTime<Utc>>,
+) -> Result<()> {
+    if let Some(until) = timeouts.remove(&member.user.id)
+        && until > now
+    {
+        lift_timeout(ctx, member.clone(), until).await?;
+    }
+    Ok(())
+}
+
+/// Returns whether the member had to be disconnected
+async fn enforce_bedtime(ctx: &Context, data: &impl With<ConfigT>, cfg: &ConfigT, member: Member) -> Result<bool> {
     let name = member.display_name();
 
     if let Some(bedtime_role) = cfg.role
@@ -92,6 +196,30 @@ async fn enforce_bedtime(ctx: &Context, cfg: &ConfigT, member: Member) -> Result
         member.add_role(&ctx, bedtime_role).await?;
     };
 
+    if cfg.block_connect {
+        let user_id = member.user.id;
+        // channels where the member already has an overwrite are left alone, it might be a moderator's
+        for channel_id in voice_channels(ctx, cfg, member.guild_id, |overwrites| {
+            !overwrites.iter().any(|o| o.kind == PermissionOverwriteType::Member(user_id))
+        })? {
+            tracing::info!("🌙 Blocking {name} from joining {channel_id}");
+            channel_id.create_permission(&ctx, connect_block(user_id)).await?;
+            data.with_mut_ok(|cfg| cfg.blocks.entry(user_id).or_default().insert(channel_id)).await?;
+        }
+    }
+
+    if enforced_voice_channel(ctx, cfg, &member)?.is_none() {
+        return Ok(false);
+    }
+
+    tracing::info!("🌙 Disconnecting {name}");
+    member.guild_id.disconnect_member(&ctx, member.user.id).await?;
+
+    Ok(true)
+}
+
+/// The voice channel the member is in, unless bedtimes aren't enforced there
+fn enforced_voice_channel(ctx: &Context, cfg: &ConfigT, member: &Member) -> Result<Option<GuildChannel>> {
     let Some(channel) = ({
         let guild = ctx.cache.guild(member.guild_id).some()?;
         guild
@@ -101,24 +229,54 @@ async fn enforce_bedtime(ctx: &Context, cfg: &ConfigT, member: Member) -> Result
             .and_then(|id| guild.channels.get(&id))
             .cloned()
     }) else {
-        return Ok(());
+        return Ok(None);
     };
 
-    // don't disconnect users in voice channels with specific status
-    if let Some(status) = channel.status
-        && let Some(re) = &cfg.ignored_vc_description
-        && re.0.is_match(&status)?
-    {
-        return Ok(());
-    };
+    if is_ignored(cfg, &channel)? {
+        return Ok(None);
+    }
 
-    tracing::info!("🌙 Disconnecting {name}");
-    member.guild_id.disconnect_member(&ctx, member.user.id).await?;
+    Ok(Some(channel))
+}
 
-    Ok(())
+/// Don't enforce bedtimes in voice channels with specific status
+fn is_ignored(cfg: &ConfigT, channel: &GuildChannel) -> Result<bool> {
+    Ok(match (&channel.status, &cfg.ignored_vc_description) {
+        (Some(status), Some(re)) => re.0.is_match(status)?,
+        _ => false,
+    })
 }
 
-async fn lift_bedtime(ctx: &Context, cfg: &ConfigT, member: Member) -> Result<()> {
+/// Keeps a member from joining a voice channel, it doesn't merge with other overwrites of the member
+fn connect_block(user_id: UserId) -> PermissionOverwrite {
+    PermissionOverwrite {
+        allow: Permissions::empty(),
+        deny: Permissions::CONNECT,
+        kind: PermissionOverwriteType::Member(user_id),
+    }
+}
+
+/// The enforced voice channels whose permission overwrites match the filter
+fn voice_channels(
+    ctx: &Context,
+    cfg: &ConfigT,
+    guild_id: GuildId,
+    filter: impl Fn(&[PermissionOverwrite]) -> bool,
+) -> Result<Vec<ChannelId>> {
+    let guild = ctx.cache.guild(guild_id).some()?;
+    let mut channels = vec![];
+    for channel in guild.channels.values() {
+        if matches!(channel.kind, ChannelType::Voice | ChannelType::Stage)
+            && filter(&channel.permission_overwrites)
+            && !is_ignored(cfg, channel)?
+        {
+            channels.push(channel.id);
+        }
+    }
+    Ok(channels)
+}
+
+async fn lift_bedtime(ctx: &Context, data: &impl With<ConfigT>, cfg: &ConfigT, member: &Member) -> Result<()> {
     let name = member.display_name();
 
     if let Some(role) = cfg.role
@@ -128,5 +286,32 @@ async fn lift_bedtime(ctx: &Context, cfg: &ConfigT, member: Member) -> Result<()
         member.remove_role(&ctx, role).await?;
     }
 
-    Ok(())
+    let user_id = member.user.id;
+    let Some(blocks) = cfg.blocks.get(&user_id) else { return Ok(()) };
+    // overwrites that were changed since, or whose channel is gone, are only forgotten
+    let blocked: Vec<ChannelId> = {
+        let guild = ctx.cache.guild(member.guild_id).some()?;
+        blocks
+            .iter()
+            .filter(|id| {
+                guild.channels.get(id).is_some_and(|c| c.permission_overwrites.contains(&connect_block(user_id)))
+            })
+            .copied()
+            .collect()
+    };
+    for &channel_id in &blocked {
+        tracing::info!("🌙 Unblocking {name} from joining {channel_id}");
+        channel_id.delete_permission(&ctx, PermissionOverwriteType::Member(user_id)).await?;
+    }
+
+    let lifted = blocks.clone();
+    data.with_mut_ok(|cfg| {
+        if let Some(blocks) = cfg.blocks.get_mut(&user_id) {
+            blocks.retain(|id| !lifted.contains(id));
+            if blocks.is_empty() {
+                cfg.blocks.remove(&user_id);
+            }
+        }
+    })
+    .await
 }
@@ -0,0 +1,126 @@
+use crate::ConfigT;
+use crate::warnings::render;
+use bot_core::serde::LiteralRegex;
+use bot_core::{EvtContext, State, With};
+use chrono::{DateTime, TimeDelta, Utc};
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use eyre::Result;
+use poise::serenity_prelude::{
+    Activity, ActivityType, Context, CreateMessage, Member, Mentionable as _, Presence, UserId,
+};
+use std::time::Instant;
+
+/// Opt-in restrictions of a bedtime in addition to voice disconnects
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Restriction {
+    /// Time out the member in text channels until the bedtime ends
+    Timeout,
+    /// DM the member when they start playing a game
+    GameNudge,
+}
+
+impl Restriction {
+    pub(crate) const ALL: [Restriction; 2] = [Restriction::Timeout, Restriction::GameNudge];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Restriction::Timeout => "timeout",
+            Restriction::GameNudge => "game_nudge",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| r.name() == name)
+    }
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Restriction::Timeout => "🔇 Timeout",
+            Restriction::GameNudge => "🎮 Game nudge",
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, sensible::Default)]
+pub(crate) struct NudgeConfig {
+    /// Games to nudge about, defaults to all games
+    games: Option<LiteralRegex>,
+    /// Template with the variables `{name}` and `{game}`
+    #[default("🎮 {name}, it's your bedtime, time to put {game} away".to_string())]
+    message: String,
+    #[serde(with = "bot_core::serde::td_seconds")]
+    #[default(TimeDelta::minutes(30))]
+    cooldown: TimeDelta,
+}
+
+#[derive(Default)]
+pub struct StateT {
+    /// When each member may be nudged again
+    nudged: DashMap<UserId, Instant>,
+}
+
+pub(crate) async fn timeout(ctx: &Context, mut member: Member, until: DateTime<Utc>) -> Result<()> {
+    tracing::info!("🌙 Timing out {} until {until}", member.display_name());
+    member.disable_communication_until_datetime(ctx, until.into()).await?;
+    Ok(())
+}
+
+/// Lift a timeout early, unless someone else changed it in the meantime
+pub(crate) async fn lift_timeout(ctx: &Context, mut member: Member, until: DateTime<Utc>) -> Result<()> {
+    if member.communication_disabled_until.is_none_or(|t| t.unix_timestamp() != until.timestamp()) {
+        return Ok(());
+    }
+    tracing::info!("🌙 Lifting timeout of {}", member.display_name());
+    member.enable_communication(ctx).await?;
+    Ok(())
+}
+
+/// DM the member if one of their activities is a game, at most once per cooldown
+pub(crate) async fn nudge(
+    ctx: &Context,
+    data: &impl State<StateT>,
+    cfg: &NudgeConfig,
+    user_id: UserId,
+    activities: &[Activity],
+) -> Result<()> {
+    let mut game = None;
+    for activity in activities.iter().filter(|a| a.kind == ActivityType::Playing) {
+        if cfg.games.as_ref().map_or(Ok(true), |re| re.0.is_match(&activity.name))? {
+            game = Some(&activity.name);
+            break;
+        }
+    }
+    let Some(game) = game else { return Ok(()) };
+
+    let now = Instant::now();
+    match data.state().nudged.entry(user_id) {
+        Entry::Occupied(entry) if *entry.get() > now => return Ok(()),
+        entry => _ = entry.insert(now + cfg.cooldown.to_std()?),
+    }
+
+    tracing::info!("🎮 Nudging {user_id} to stop playing {game}");
+    let content = render(&cfg.message, &[("name", user_id.mention().to_string()), ("game", game.clone())]);
+    user_id.direct_message(ctx, CreateMessage::new().content(content)).await?;
+
+    Ok(())
+}
+
+pub async fn on_presence_update(
+    ctx: EvtContext<'_, impl With<ConfigT> + State<StateT>>,
+    presence: &Presence,
+) -> Result<()> {
+    let user_id = presence.user.id;
+    let Some(cfg) = ctx
+        .user_data
+        .with_ok(|cfg| {
+            cfg.restrictions(user_id, Utc::now()).contains(&Restriction::GameNudge).then(|| cfg.nudge.clone())
+        })
+        .await?
+    else {
+        return Ok(());
+    };
+
+    nudge(ctx.serenity_context, ctx.user_data, &cfg, user_id, &presence.activities).await
+}
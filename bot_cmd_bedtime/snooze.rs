@@ -0,0 +1,97 @@
+use crate::approval::partner_approval;
+use crate::{ConfigT, SNOOZE_BUTTON_ID};
+use bot_core::ext::create_reply::CreateReplyExt;
+use bot_core::{EvtContext, With};
+use chrono::{DateTime, TimeDelta, Utc};
+use eyre::{OptionExt as _, Result, ensure};
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    ButtonStyle, ComponentInteraction, CreateButton, Mentionable as _, ReactionType, UserId,
+};
+
+/// How long before a bedtime it can already be snoozed
+const SNOOZABLE_BEFORE: TimeDelta = TimeDelta::hours(1);
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, sensible::Default)]
+pub(crate) struct SnoozeConfig {
+    /// Snoozes per night
+    #[default(2)]
+    count: u32,
+    #[serde(with = "bot_core::serde::td_seconds")]
+    #[default(TimeDelta::minutes(15))]
+    duration: TimeDelta,
+}
+
+/// A user's snoozes of one night, the bedtime is lifted from `start` until `until`
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Snooze {
+    pub(crate) start: DateTime<Utc>,
+    pub(crate) until: DateTime<Utc>,
+    count: u32,
+}
+
+pub(crate) fn snooze_button(user_id: UserId) -> CreateButton {
+    CreateButton::new(format!("{SNOOZE_BUTTON_ID}:{user_id}"))
+        .style(ButtonStyle::Secondary)
+        .emoji(ReactionType::Unicode("💤".to_string()))
+}
+
+pub async fn btn_snooze(
+    ctx: EvtContext<'_, impl With<ConfigT>>,
+    component: &ComponentInteraction,
+    param: &str,
+) -> Result<()> {
+    let user_id: UserId = param.parse()?;
+    ensure!(component.user.id == user_id, "That's not your own bedtime");
+
+    // check that snoozing is possible before bothering the partner
+    let partner = ctx
+        .user_data
+        .with(|cfg| {
+            snooze(&mut cfg.clone(), user_id, Utc::now())?;
+            Ok(cfg.partners.get(&user_id).copied())
+        })
+        .await?;
+
+    if let Some(partner) = partner
+        && !partner_approval(ctx.serenity_context, component, partner, "snooze their bedtime").await?
+    {
+        return Ok(());
+    }
+
+    let until = ctx.user_data.with_mut(|cfg| snooze(cfg, user_id, Utc::now())).await?;
+    tracing::info!("💤 Snoozed bedtime of {user_id} until {until}");
+
+    let reply = CreateReply::new().content(format!(
+        "💤 {} snoozed their bedtime until <t:{}:t>",
+        user_id.mention(),
+        until.timestamp()
+    ));
+    match partner {
+        Some(_) => _ = reply.followup_to_component(ctx.serenity_context, component).await?,
+        None => reply.respond_to_component(ctx.serenity_context, component).await?,
+    }
+
+    Ok(())
+}
+
+/// Snooze the active or upcoming bedtime of the user, returns when the bedtime starts now
+fn snooze(cfg: &mut ConfigT, user_id: UserId, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let intervals = cfg.intervals(now).remove(&user_id).unwrap_or_default();
+    let start = intervals
+        .find(now)
+        .map(|interval| interval.start)
+        .or_else(|| intervals.next_start(now).filter(|&start| start - now <= SNOOZABLE_BEFORE))
+        .ok_or_eyre("There is no bedtime to snooze right now")?;
+
+    // an earlier snooze of the same night ends where the bedtime starts now
+    let snooze = match cfg.snoozes.get(&user_id) {
+        Some(snooze) if snooze.until == start => snooze.clone(),
+        _ => Snooze { start, until: start, count: 0 },
+    };
+    ensure!(snooze.count < cfg.snooze.count, "You can't snooze more than {} times per night", cfg.snooze.count);
+
+    let until = snooze.until.max(now) + cfg.snooze.duration;
+    cfg.snoozes.insert(user_id, Snooze { start: snooze.start, until, count: snooze.count + 1 });
+    Ok(until)
+}
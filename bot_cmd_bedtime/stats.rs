@@ -0,0 +1,223 @@
+use crate::ConfigT;
+use bot_core::With;
+use bot_core::time::iso_week::IsoWeekSerde;
+use bot_core::time::local_timezone;
+use chrono::{DateTime, Datelike, NaiveDate, TimeDelta, Utc, Weekday};
+use chrono_tz::Tz;
+use eyre::Result;
+use itertools::Itertools as _;
+use poise::serenity_prelude::{
+    Color, Context, CreateEmbed, CreateEmbedFooter, CreateMessage, Mentionable as _, UserId,
+};
+use std::collections::BTreeMap;
+
+/// How long nights are kept for the stats, they're part of the config so this is kept short
+const HISTORY_DAYS: i64 = 56;
+
+/// Members per weekly report message, keeps the embeds well below Discord's length limit
+const REPORT_PAGE_SIZE: usize = 40;
+
+/// What happened during a member's bedtime
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Default)]
+pub(crate) struct Night {
+    /// They were still in voice when the bedtime started
+    disconnected_at_start: bool,
+    /// Times they joined voice again during the bedtime and had to be disconnected
+    rejoins: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    Offline,
+    Disconnected,
+    Rejoined,
+}
+
+impl Night {
+    fn outcome(&self) -> Outcome {
+        if self.rejoins > 0 {
+            Outcome::Rejoined
+        } else if self.disconnected_at_start {
+            Outcome::Disconnected
+        } else {
+            Outcome::Offline
+        }
+    }
+}
+
+impl Outcome {
+    fn emoji(self) -> char {
+        match self {
+            Outcome::Offline => '🟩',
+            Outcome::Disconnected => '🟨',
+            Outcome::Rejoined => '🟥',
+        }
+    }
+}
+
+/// The date of the evening a bedtime belongs to, so that bedtimes after midnight count for the previous day
+pub(crate) fn night_of(start: DateTime<Utc>, tz: Tz) -> NaiveDate {
+    (start.with_timezone(&tz) - TimeDelta::hours(12)).date_naive()
+}
+
+/// Record that a member had bedtime this minute and whether they had to be disconnected
+pub(crate) async fn record_nights(
+    data: &impl With<ConfigT>,
+    cfg: &ConfigT,
+    observations: Vec<(UserId, NaiveDate, bool)>,
+) -> Result<()> {
+    let changes = observations
+        .into_iter()
+        .filter(|(user_id, night, disconnected)| {
+            *disconnected || !cfg.history.get(user_id).is_some_and(|nights| nights.contains_key(night))
+        })
+        .collect_vec();
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    data.with_mut_ok(|cfg| {
+        for (user_id, night, disconnected) in changes {
+            let nights = cfg.history.entry(user_id).or_default();
+            match nights.get_mut(&night) {
+                Some(record) if disconnected => record.rejoins += 1,
+                Some(_) => {}
+                None => _ = nights.insert(night, Night { disconnected_at_start: disconnected, rejoins: 0 }),
+            }
+            prune_history(nights, night);
+        }
+    })
+    .await
+}
+
+/// Record that a member joined voice during their bedtime and had to be disconnected
+pub(crate) async fn record_rejoin(data: &impl With<ConfigT>, user_id: UserId, night: NaiveDate) -> Result<()> {
+    data.with_mut_ok(|cfg| {
+        let nights = cfg.history.entry(user_id).or_default();
+        // a join is never the start of the bedtime, even if it's the first thing recorded that night
+        nights.entry(night).or_default().rejoins += 1;
+        prune_history(nights, night);
+    })
+    .await
+}
+
+fn prune_history(nights: &mut BTreeMap<NaiveDate, Night>, night: NaiveDate) {
+    nights.retain(|&date, _| date >= night - TimeDelta::days(HISTORY_DAYS));
+}
+
+#[derive(Debug, PartialEq, Default)]
+struct Stats {
+    nights: usize,
+    on_time: usize,
+    streak: usize,
+    best_streak: usize,
+    /// (on time, total) per weekday, starting on Monday
+    by_weekday: [(usize, usize); 7],
+}
+
+fn stats<'a>(nights: impl IntoIterator<Item = (&'a NaiveDate, &'a Night)>) -> Stats {
+    let mut stats = Stats::default();
+    for (date, night) in nights {
+        let on_time = night.outcome() == Outcome::Offline;
+        let weekday = &mut stats.by_weekday[date.weekday().num_days_from_monday() as usize];
+        stats.nights += 1;
+        weekday.1 += 1;
+        if on_time {
+            stats.on_time += 1;
+            weekday.0 += 1;
+            stats.streak += 1;
+            stats.best_streak = stats.best_streak.max(stats.streak);
+        } else {
+            stats.streak = 0;
+        }
+    }
+    stats
+}
+
+pub(crate) fn stats_embed(user_id: UserId, nights: &BTreeMap<NaiveDate, Night>) -> CreateEmbed {
+    let stats = stats(nights);
+    let heatmap = stats
+        .by_weekday
+        .iter()
+        .zip([Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun])
+        .map(|(&(on_time, total), weekday)| {
+            let square = match (on_time * 100).checked_div(total) {
+                None => '⬛',
+                Some(80..) => '🟩',
+                Some(50..) => '🟨',
+                Some(_) => '🟥',
+            };
+            format!("`{weekday}` {square} {on_time}/{total}")
+        })
+        .join("\n");
+    let recent = nights.values().rev().take(14).rev().map(|night| night.outcome().emoji()).collect::<String>();
+
+    CreateEmbed::new()
+        .title("🌙 Bedtime Stats")
+        .description(format!("{}\n{recent}", user_id.mention()))
+        .field("Streak", format!("🔥 {} nights (best: {})", stats.streak, stats.best_streak), true)
+        .field("On time", format!("{}% of {} nights", stats.on_time * 100 / stats.nights.max(1), stats.nights), true)
+        .field("Weekdays", heatmap, false)
+        .footer(CreateEmbedFooter::new(format!("🟩 offline  🟨 disconnected  🟥 rejoined · last {HISTORY_DAYS} days")))
+        .color(Color::DARK_PURPLE)
+}
+
+/// Post a summary of last week's nights once a week
+pub(crate) async fn weekly_report(ctx: &Context, data: &impl With<ConfigT>, cfg: &ConfigT) -> Result<()> {
+    let Some(channel) = cfg.report_channel else { return Ok(()) };
+    let today = Utc::now().with_timezone(&local_timezone()).date_naive();
+    let this_week = IsoWeekSerde(today.iso_week());
+    if cfg.last_report == Some(this_week) {
+        return Ok(());
+    }
+    let last_week = (today - TimeDelta::weeks(1)).iso_week();
+
+    let lines = cfg
+        .history
+        .iter()
+        .map(|(user_id, nights)| {
+            let week = stats(nights.iter().filter(|(date, _)| date.iso_week() == last_week));
+            (user_id, week, stats(nights).streak)
+        })
+        .filter(|(_, week, _)| week.nights > 0)
+        .sorted_by_key(|(_, week, _)| (week.nights - week.on_time, week.nights))
+        .map(|(user_id, week, streak)| {
+            format!("{}: {}/{} nights on time, 🔥 {streak}", user_id.mention(), week.on_time, week.nights)
+        })
+        .collect_vec();
+
+    let pages = lines.len().div_ceil(REPORT_PAGE_SIZE);
+    for (i, page) in lines.chunks(REPORT_PAGE_SIZE).enumerate() {
+        let mut title = format!("🌙 Bedtime Report – Week {}", last_week.week());
+        if pages > 1 {
+            title += &format!(" ({}/{pages})", i + 1);
+        }
+        let embed = CreateEmbed::new().title(title).description(page.join("\n")).color(Color::DARK_PURPLE);
+        channel.send_message(ctx, CreateMessage::new().embed(embed)).await?;
+    }
+
+    data.with_mut_ok(|cfg| cfg.last_report = Some(this_week)).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn streaks_and_weekdays() {
+        let offline = Night::default();
+        let late = Night { disconnected_at_start: true, rejoins: 0 };
+        let date = |day| NaiveDate::from_ymd_opt(2025, 1, day).unwrap();
+        let nights = BTreeMap::from([
+            (date(6), offline.clone()),
+            (date(7), offline.clone()),
+            (date(8), late),
+            (date(13), offline.clone()),
+            (date(14), offline),
+        ]);
+        let stats = stats(&nights);
+        assert_eq!((stats.nights, stats.on_time, stats.streak, stats.best_streak), (5, 4, 2, 2));
+        assert_eq!(stats.by_weekday[0], (2, 2));
+        assert_eq!(stats.by_weekday[2], (0, 1));
+    }
+}
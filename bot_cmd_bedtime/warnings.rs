@@ -0,0 +1,101 @@
+use crate::ConfigT;
+use crate::snooze::snooze_button;
+use bot_core::State;
+use bot_core::template::{self, Chunk};
+use chrono::TimeDelta;
+use eyre::Result;
+use poise::serenity_prelude::{Context, CreateActionRow, CreateMessage, GuildChannel, Member, Mentionable as _};
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Warning {
+    #[serde(with = "bot_core::serde::td_seconds")]
+    before: TimeDelta,
+    kind: WarningKind,
+    /// Template with the variables `{name}` and `{minutes}`
+    message: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum WarningKind {
+    Dm,
+    /// The text chat of the member's voice channel
+    VoiceChat,
+    /// TTS announcement in the member's voice channel
+    Tts,
+}
+
+pub(crate) fn default_warnings() -> Vec<Warning> {
+    vec![
+        Warning {
+            before: TimeDelta::minutes(15),
+            kind: WarningKind::VoiceChat,
+            message: "🌙 {name}, your bedtime starts in {minutes} minutes".to_string(),
+        },
+        Warning {
+            before: TimeDelta::minutes(5),
+            kind: WarningKind::Tts,
+            message: "{name}, bedtime in {minutes} minutes".to_string(),
+        },
+    ]
+}
+
+/// Warn a member in voice whose bedtime starts in `until`.
+/// Called once a minute, so each warning fires in the minute before its time is reached.
+pub(crate) async fn warn_bedtime(
+    ctx: &Context,
+    data: &impl State<bot_core::audio::StateT>,
+    cfg: &ConfigT,
+    member: &Member,
+    channel: &GuildChannel,
+    until: TimeDelta,
+) -> Result<()> {
+    let Some(warning) = cfg.warnings.iter().find(|w| until <= w.before && until > w.before - TimeDelta::minutes(1))
+    else {
+        return Ok(());
+    };
+
+    let minutes = (until.num_seconds() + 59) / 60;
+    tracing::info!("🌙 Warning {} about their bedtime in {minutes} minutes", member.display_name());
+
+    let message = || {
+        CreateMessage::new()
+            .content(render(
+                &warning.message,
+                &[("name", member.mention().to_string()), ("minutes", minutes.to_string())],
+            ))
+            .components(vec![CreateActionRow::Buttons(vec![snooze_button(member.user.id)])])
+    };
+    match warning.kind {
+        WarningKind::Dm => {
+            member.user.direct_message(ctx, message()).await?;
+        }
+        WarningKind::VoiceChat => {
+            channel.id.send_message(ctx, message()).await?;
+        }
+        WarningKind::Tts => {
+            let text = render(
+                &warning.message,
+                &[("name", member.display_name().to_string()), ("minutes", minutes.to_string())],
+            );
+            let audio = bot_core::tts::get_tts(&text).await?;
+            bot_core::audio::play(ctx, data, member.guild_id, channel.id, audio).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fill in the template variables, unknown ones are kept as they are
+pub(crate) fn render(message: &str, vars: &[(&str, String)]) -> String {
+    template::template_to_chunks(message)
+        .into_iter()
+        .map(|chunk| match chunk {
+            Chunk::Text(text) => text,
+            Chunk::Variable(var) => match vars.iter().find(|(name, _)| *name == var) {
+                Some((_, value)) => value.clone(),
+                None => format!("{{{var}}}"),
+            },
+        })
+        .collect()
+}
@@ -1,25 +1,41 @@
+use crate::ledger::{Transaction, TransactionKind};
 use crate::{ACCOUNT_BUTTON_ID, ConfigT, Currency, DailyIncome, TABLE_SELECT_ID};
 use bot_core::ext::create_reply::CreateReplyExt;
 use bot_core::ext::option::OptionExt as _;
 use bot_core::{CmdContext, EvtContext, With, avatar_url};
-use chrono::{DateTime, Datelike, Local, TimeZone};
-use eyre::{OptionExt, Result};
+use chrono::{DateTime, Datelike, Local, TimeZone, Utc};
+use eyre::{OptionExt, Result, ensure};
 use itertools::Itertools;
 use poise::CreateReply;
 use poise::serenity_prelude::{
     ButtonStyle, ChannelType, Colour, ComponentInteraction, ComponentInteractionDataKind, CreateActionRow,
-    CreateButton, CreateEmbed, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, Member,
+    CreateButton, CreateEmbed, CreateEmbedFooter, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
+    Member, Mentionable as _, UserId,
 };
 use std::collections::BTreeMap;
+use std::time::Duration;
 use uuid::Uuid;
 
+const HISTORY_PAGE_SIZE: usize = 15;
+
+/// Manage your account
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("account_balance", "account_history", "account_audit"),
+    subcommand_required
+)]
+pub async fn account<D: With<ConfigT>>(_ctx: CmdContext<'_, D>) -> Result<()> {
+    Ok(())
+}
+
 /// Check your balance and claim your income
-#[poise::command(slash_command, guild_only)]
-pub async fn account<D: With<ConfigT>>(ctx: CmdContext<'_, D>, user: Option<Member>) -> Result<()> {
+#[poise::command(slash_command, guild_only, rename = "balance")]
+pub async fn account_balance<D: With<ConfigT>>(ctx: CmdContext<'_, D>, user: Option<Member>) -> Result<()> {
     let (reply, mut components) = account_reply(ctx.data(), ctx.author_member().await.some()?.as_ref(), user).await?;
 
     components.push(CreateActionRow::Buttons(vec![
-        CreateButton::new(ACCOUNT_BUTTON_ID).style(ButtonStyle::Primary).label("/account"),
+        CreateButton::new(ACCOUNT_BUTTON_ID).style(ButtonStyle::Primary).label("/account balance"),
     ]));
 
     ctx.send(reply.components(components)).await?;
@@ -27,6 +43,115 @@ pub async fn account<D: With<ConfigT>>(ctx: CmdContext<'_, D>, user: Option<Memb
     Ok(())
 }
 
+/// View the transactions of your account
+#[poise::command(slash_command, guild_only, rename = "history")]
+pub async fn account_history<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "Member, defaults to you"] user: Option<UserId>,
+) -> Result<()> {
+    let user_id = user.unwrap_or(ctx.author().id);
+    let cur = Currency::read(ctx.data()).await?;
+    let transactions =
+        ctx.data().with_ok(|cfg| cfg.ledger.iter().filter(|t| t.user == user_id).rev().cloned().collect_vec()).await?;
+    ensure!(!transactions.is_empty(), "{} has no transactions yet", user_id.mention());
+
+    let pages = transactions.len().div_ceil(HISTORY_PAGE_SIZE);
+    let prev_id = "~economy.history_prev";
+    let next_id = "~economy.history_next";
+    let reply = |page: usize, active: bool| {
+        let lines = transactions
+            .iter()
+            .skip(page * HISTORY_PAGE_SIZE)
+            .take(HISTORY_PAGE_SIZE)
+            .map(|t| format!("<t:{}:d> **{}** {}", t.time.timestamp(), cur.fmt_signed(t.amount), t.describe()))
+            .join("\n");
+        let embed = CreateEmbed::new()
+            .title("📜 History")
+            .description(format!("{}\n\n{lines}", user_id.mention()))
+            .footer(CreateEmbedFooter::new(format!("Page {}/{pages}", page + 1)))
+            .colour(if active { Colour::BLITZ_BLUE } else { Colour::DARKER_GREY });
+        let buttons = vec![
+            CreateButton::new(prev_id).emoji('◀').style(ButtonStyle::Secondary).disabled(page == 0),
+            CreateButton::new(next_id).emoji('▶').style(ButtonStyle::Secondary).disabled(page + 1 >= pages),
+        ];
+        CreateReply::new().embed(embed).components(if active && pages > 1 {
+            vec![CreateActionRow::Buttons(buttons)]
+        } else {
+            vec![]
+        })
+    };
+
+    let handle = ctx.send(reply(0, true)).await?;
+    let message = handle.message().await?;
+
+    let mut page = 0;
+    while let Some(interaction) = message
+        .await_component_interaction(ctx.serenity_context())
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(5 * 60))
+        .await
+    {
+        page = if interaction.data.custom_id == prev_id { page.saturating_sub(1) } else { (page + 1).min(pages - 1) };
+        reply(page, true).update_to_component(ctx.serenity_context(), &interaction).await?;
+    }
+
+    handle.edit(ctx, reply(page, false)).await?;
+
+    Ok(())
+}
+
+/// Check that all balances match the transactions in the ledger
+#[poise::command(
+    slash_command,
+    guild_only,
+    rename = "audit",
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn account_audit<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "Record adjustments so that the ledger matches the balances"] fix: Option<bool>,
+) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let audit = ctx
+        .data()
+        .with_mut(|cfg| {
+            let audit = cfg.audit();
+            if fix.unwrap_or(false) {
+                for (&user, &(balance, sum)) in &audit.accounts {
+                    let amount = i64::try_from(i128::from(balance) - sum)?;
+                    cfg.ledger.push(Transaction { time: Utc::now(), user, amount, kind: TransactionKind::Adjustment });
+                }
+            }
+            Ok(audit)
+        })
+        .await?;
+
+    if audit.accounts.is_empty() && audit.pools.is_empty() {
+        ctx.say("✅ All balances match the ledger").await?;
+        return Ok(());
+    }
+
+    let fmt_sum = |sum: i128| i64::try_from(sum).map_or(sum.to_string(), |sum| cur.fmt_signed(sum));
+    let lines = audit
+        .accounts
+        .iter()
+        .map(|(user, &(balance, sum))| format!("{}: {} ≠ {}", user.mention(), cur.fmt(balance), fmt_sum(sum)))
+        .chain(
+            audit.pools.iter().map(|(pool, &(pot, sum))| format!("Pool {pool}: {} ≠ {}", cur.fmt(pot), fmt_sum(sum))),
+        )
+        .join("\n");
+    let title = if fix.unwrap_or(false) {
+        "⚠️ Mismatches (balance ≠ ledger), recorded adjustments for the accounts"
+    } else {
+        "⚠️ Mismatches (balance ≠ ledger)"
+    };
+    ctx.send(CreateReply::new().embed(CreateEmbed::new().title(title).description(lines).colour(Colour::ORANGE)))
+        .await?;
+
+    Ok(())
+}
+
 pub async fn btn_account(ctx: EvtContext<'_, impl With<ConfigT>>, component: &ComponentInteraction) -> Result<()> {
     let (reply, components) = account_reply(ctx.user_data, component.member.as_ref().some()?, None).await?;
 
@@ -62,8 +187,8 @@ async fn account_reply(
     let member = member.as_ref().unwrap_or(author);
 
     let cur = Currency::read(data).await?;
-    let (account, rewarded_days, income, tables) = data
-        .with_mut_ok(|cfg| {
+    let (account, rewarded_days, income, repaid, tables, earnings, overdue) = data
+        .with_mut(|cfg| {
             let account = cfg.account.entry(member.user.id).or_default();
 
             let now = Local::now();
@@ -71,11 +196,14 @@ async fn account_reply(
             let rewarded_days = rewarded_days(&cfg.daily_income, last_claim, now);
             let income = (rewarded_days as u64) * cfg.daily_income.amount;
 
-            // claim income for yourself
+            // claim income for yourself, due loans are repaid from it
+            let mut repaid = 0;
             if income != 0 && member.user.id == author.user.id {
                 account.last_claim = Some(now.into());
-                account.balance += income;
+                cfg.transact(member.user.id, income.try_into()?, TransactionKind::Income { days: rewarded_days })?;
+                repaid = cfg.repay_loans(member.user.id, income, true)?;
             }
+            let account = cfg.account.get(&member.user.id).some()?;
 
             // tables the user is involved in
             let tables = cfg
@@ -85,7 +213,15 @@ async fn account_reply(
                 .map(|(id, t)| (*id, t.clone()))
                 .collect::<BTreeMap<_, _>>();
 
-            (account.clone(), rewarded_days, income, tables)
+            let earnings = cfg.earnings_today(member.user.id).cloned();
+
+            let overdue = cfg
+                .loans_of(member.user.id)
+                .into_iter()
+                .filter(|loan| loan.borrower == member.user.id && loan.due <= Utc::now())
+                .collect_vec();
+
+            Ok((account.clone(), rewarded_days, income, repaid, tables, earnings, overdue))
         })
         .await?;
 
@@ -102,6 +238,20 @@ async fn account_reply(
         embed = embed.field(title, income_str, true)
     }
 
+    if repaid != 0 {
+        embed = embed.field("Loan Repayment", format!("-{}", cur.fmt(repaid)), true);
+    }
+
+    if let Some(earnings) = earnings.filter(|e| e.messages != 0 || e.voice != 0) {
+        let breakdown = format!("💬 Messages: +{}\n🎙️ Voice: +{}", cur.fmt(earnings.messages), cur.fmt(earnings.voice));
+        embed = embed.field("Activity today", breakdown, true);
+    }
+
+    if !overdue.is_empty() {
+        let debts = overdue.iter().map(|loan| loan.describe(&cur, member.user.id)).join("\n");
+        embed = embed.field("Overdue Debts", debts, false);
+    }
+
     let mut components = vec![];
 
     // add a selection menu to view tables you're involved in
@@ -0,0 +1,205 @@
+use crate::ledger::{Transaction, TransactionKind};
+use crate::{ConfigT, StateT};
+use bot_core::ext::option::OptionExt as _;
+use bot_core::{EvtContext, State, With};
+use chrono::{Local, NaiveDate, TimeDelta, Utc};
+use eyre::Result;
+use itertools::Itertools;
+use poise::serenity_prelude::{Context, Guild, GuildId, Message, UserId, VoiceState};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Income for being active, credited right away
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, sensible::Default)]
+pub(crate) struct ActivityIncome {
+    per_message: u64,
+    /// Messages sent sooner than this after the last paid one earn nothing
+    #[serde(with = "bot_core::serde::td_seconds")]
+    #[default(TimeDelta::minutes(1))]
+    message_cooldown: TimeDelta,
+    /// Shorter messages earn nothing
+    #[default(5)]
+    min_message_length: usize,
+    daily_message_cap: Option<u64>,
+    /// Paid for each minute in a voice channel, except while deafened or in the AFK channel
+    per_voice_minute: u64,
+    daily_voice_cap: Option<u64>,
+}
+
+/// What a member earned from activity on a day
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Default)]
+pub(crate) struct Earnings {
+    pub(crate) day: NaiveDate,
+    pub(crate) messages: u64,
+    pub(crate) voice: u64,
+}
+
+enum Source {
+    Messages,
+    Voice,
+}
+
+impl ConfigT {
+    /// Credits activity income up to the daily cap and returns how much was credited.
+    /// Each member gets a single ledger entry per day that adds up all of it.
+    fn credit_activity(&mut self, user: UserId, source: Source, amount: u64) -> Result<u64> {
+        let today = Local::now().date_naive();
+        let earnings = self.activity_earnings.entry(user).or_default();
+        if earnings.day != today {
+            *earnings = Earnings { day: today, ..Default::default() };
+        }
+        let (earned, cap) = match source {
+            Source::Messages => (&mut earnings.messages, self.activity_income.daily_message_cap),
+            Source::Voice => (&mut earnings.voice, self.activity_income.daily_voice_cap),
+        };
+        let amount = cap.map_or(amount, |cap| amount.min(cap.saturating_sub(*earned)));
+        if amount == 0 {
+            return Ok(0);
+        }
+        *earned += amount;
+
+        self.account.entry(user).or_default().balance += amount;
+        let todays_entry = self
+            .ledger
+            .iter_mut()
+            .rev()
+            .take_while(|t| t.time.with_timezone(&Local).date_naive() == today)
+            .find(|t| t.user == user && t.kind == TransactionKind::Activity);
+        match todays_entry {
+            Some(transaction) => transaction.amount += i64::try_from(amount)?,
+            None => self.ledger.push(Transaction {
+                time: Utc::now(),
+                user,
+                amount: amount.try_into()?,
+                kind: TransactionKind::Activity,
+            }),
+        }
+
+        Ok(amount)
+    }
+
+    /// Today's activity earnings of the member
+    pub(crate) fn earnings_today(&self, user: UserId) -> Option<&Earnings> {
+        self.activity_earnings.get(&user).filter(|e| e.day == Local::now().date_naive())
+    }
+}
+
+pub async fn on_message(ctx: EvtContext<'_, impl With<ConfigT> + State<StateT>>, message: &Message) -> Result<()> {
+    if message.author.bot || message.guild_id.is_none() {
+        return Ok(());
+    }
+
+    let income = ctx.user_data.with_ok(|cfg| cfg.activity_income.clone()).await?;
+    if income.per_message == 0 || message.content.chars().count() < income.min_message_length {
+        return Ok(());
+    }
+
+    // anti-spam: only one message per cooldown earns anything
+    let now = Instant::now();
+    let state = ctx.user_data.state();
+    let cooldown = income.message_cooldown.to_std()?;
+    if state.last_paid_message.get(&message.author.id).is_some_and(|last| now.duration_since(*last) < cooldown) {
+        return Ok(());
+    }
+    state.last_paid_message.insert(message.author.id, now);
+
+    ctx.user_data.with_mut(|cfg| cfg.credit_activity(message.author.id, Source::Messages, income.per_message)).await?;
+
+    Ok(())
+}
+
+pub async fn on_voice_update(
+    ctx: EvtContext<'_, impl With<ConfigT> + State<StateT>>,
+    guild_id: GuildId,
+    (_old, new): (&Option<VoiceState>, &VoiceState),
+) -> Result<()> {
+    if new.member.as_ref().is_some_and(|m| m.user.bot) {
+        return Ok(());
+    }
+
+    let earning = ctx.serenity_context.cache.guild(guild_id).is_some_and(|guild| earns_in_voice(&guild, new));
+    pay_voice_session(ctx.user_data, new.user_id, earning).await
+}
+
+/// Pays the members in voice every minute, so that long sessions don't wait for a voice update.
+/// Members who were already in voice when the bot started get their sessions started here.
+pub(crate) async fn pay_voice_sessions(
+    ctx: &Context,
+    data: &(impl With<ConfigT> + State<StateT> + State<GuildId>),
+) -> Result<()> {
+    let guild_id: GuildId = *data.state();
+    let in_voice = {
+        let guild = guild_id.to_guild_cached(ctx).some()?;
+        guild
+            .voice_states
+            .values()
+            .filter(|voice| !guild.members.get(&voice.user_id).is_some_and(|m| m.user.bot))
+            .map(|voice| (voice.user_id, earns_in_voice(&guild, voice)))
+            .collect::<HashMap<_, _>>()
+    };
+
+    // sessions of members who left without an update reaching the bot end here
+    let state: Arc<StateT> = data.state();
+    let left = state.voice_sessions.iter().map(|s| *s.key()).filter(|user| !in_voice.contains_key(user)).collect_vec();
+    for (user, earning) in in_voice.into_iter().chain(left.into_iter().map(|user| (user, false))) {
+        pay_voice_session(data, user, earning).await?;
+    }
+
+    Ok(())
+}
+
+/// Whether the member is in a voice channel other than the AFK one and not deafened
+fn earns_in_voice(guild: &Guild, voice: &VoiceState) -> bool {
+    let afk_channel = guild.afk_metadata.as_ref().map(|afk| afk.afk_channel_id);
+    voice.channel_id.is_some_and(|c| Some(c) != afk_channel) && !voice.self_deaf && !voice.deaf
+}
+
+/// Pays for the time since the session started and keeps the unpaid rest of a minute for the next session
+async fn pay_voice_session(data: &(impl With<ConfigT> + State<StateT>), user: UserId, earning: bool) -> Result<()> {
+    let state: Arc<StateT> = data.state();
+    let now = Instant::now();
+    let started = state.voice_sessions.remove(&user).map(|(_, started)| started);
+    let mut next_start = now;
+    if let Some(started) = started {
+        let minutes = now.duration_since(started).as_secs() / 60;
+        next_start = started + Duration::from_secs(minutes * 60);
+        let per_minute = data.with_ok(|cfg| cfg.activity_income.per_voice_minute).await?;
+        if minutes > 0 && per_minute > 0 {
+            data.with_mut(|cfg| cfg.credit_activity(user, Source::Voice, minutes * per_minute)).await?;
+        }
+    }
+    if earning {
+        state.voice_sessions.insert(user, next_start);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn activity_income_is_capped_per_day() {
+        let user = UserId::new(1);
+        let mut cfg = ConfigT::default();
+        cfg.activity_income.daily_message_cap = Some(25);
+
+        assert_eq!(cfg.credit_activity(user, Source::Messages, 10).unwrap(), 10);
+        assert_eq!(cfg.credit_activity(user, Source::Messages, 10).unwrap(), 10);
+        assert_eq!(cfg.credit_activity(user, Source::Messages, 10).unwrap(), 5);
+        assert_eq!(cfg.credit_activity(user, Source::Messages, 10).unwrap(), 0);
+        // voice time has no cap here
+        assert_eq!(cfg.credit_activity(user, Source::Voice, 100).unwrap(), 100);
+
+        // a new day starts over
+        cfg.activity_earnings.get_mut(&user).unwrap().day -= TimeDelta::days(1);
+        assert_eq!(cfg.credit_activity(user, Source::Messages, 10).unwrap(), 10);
+
+        assert_eq!(cfg.account[&user].balance, 135);
+        // all of it is a single ledger entry for today
+        assert_eq!(cfg.ledger.len(), 1);
+        assert_eq!(cfg.ledger[0].amount, 135);
+    }
+}
@@ -0,0 +1,316 @@
+use crate::casino::FairRng;
+use crate::{ConfigT, Currency, StateT};
+use bot_core::ext::create_reply::CreateReplyExt;
+use bot_core::ext::option::OptionExt as _;
+use bot_core::{CmdContext, State, With};
+use chrono::{TimeDelta, Utc};
+use eyre::{OptionExt as _, Result, eyre};
+use itertools::Itertools;
+use poise::serenity_prelude::{
+    ButtonStyle, Colour, CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, Mentionable as _, UserId,
+};
+use poise::{CreateReply, ReplyHandle};
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+const DECKS: usize = 4;
+const MAX_PLAYERS: usize = 7;
+const JOIN_TIME: Duration = Duration::from_secs(30);
+const TURN_TIME: Duration = Duration::from_secs(60);
+const RANKS: [&str; 13] = ["A", "2", "3", "4", "5", "6", "7", "8", "9", "10", "J", "Q", "K"];
+const SUITS: [&str; 4] = ["♠", "♥", "♦", "♣"];
+
+/// Play blackjack against the bot, others can join until the cards are dealt
+#[poise::command(slash_command, guild_only)]
+pub async fn blackjack<D: With<ConfigT> + State<StateT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "How much each player bets"] wager: u64,
+) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let starter = ctx.author().id;
+    ctx.data().with_mut(|cfg| cfg.place_wager(&cur, starter, wager, "Blackjack")).await?;
+    let mut players = vec![starter];
+
+    // the wagers are already taken, so they're returned if the game can't be finished
+    let (handle, game) = match play(ctx, &cur, wager, &mut players).await {
+        Ok(played) => played,
+        Err(error) => {
+            ctx.data()
+                .with_mut(|cfg| players.iter().try_for_each(|&user| cfg.pay_winnings(user, wager, "Blackjack")))
+                .await?;
+            return Err(error);
+        }
+    };
+
+    let payouts = ctx
+        .data()
+        .with_mut(|cfg| {
+            let keep = cfg.casino.keep();
+            let payouts = game.hands.iter().map(|hand| (hand.user, game.payout(hand, keep))).collect_vec();
+            for &(user, payout) in &payouts {
+                cfg.pay_winnings(user, payout, "Blackjack")?;
+            }
+            Ok(payouts)
+        })
+        .await?;
+
+    handle.edit(ctx, game.reply(&cur, Some(&payouts)).components(vec![])).await?;
+
+    Ok(())
+}
+
+/// Lets others join and plays the hands up to the payouts, `players` are the ones whose wager was taken
+async fn play<'a, D: With<ConfigT> + State<StateT>>(
+    ctx: CmdContext<'a, D>,
+    cur: &Currency,
+    wager: u64,
+    players: &mut Vec<UserId>,
+) -> Result<(ReplyHandle<'a>, Game)> {
+    let starter = ctx.author().id;
+    let join_id = "~economy.blackjack_join";
+    let deal_id = "~economy.blackjack_deal";
+    let hit_id = "~economy.blackjack_hit";
+    let stand_id = "~economy.blackjack_stand";
+
+    let deadline = Instant::now() + JOIN_TIME;
+    let deal_at = (Utc::now() + TimeDelta::from_std(JOIN_TIME)?).timestamp();
+    let lobby = |players: &[UserId]| {
+        let description = format!(
+            "Wager: {}\nPlayers: {}\n\nThe cards are dealt <t:{deal_at}:R>",
+            cur.fmt(wager),
+            players.iter().map(|p| p.mention()).join(", ")
+        );
+        CreateReply::new()
+            .embed(CreateEmbed::new().title("🃏 Blackjack").description(description).colour(Colour::BLITZ_BLUE))
+            .components(vec![CreateActionRow::Buttons(vec![
+                CreateButton::new(join_id).label("Join").style(ButtonStyle::Primary),
+                CreateButton::new(deal_id).label("Deal").style(ButtonStyle::Success),
+            ])])
+    };
+
+    let handle = ctx.send(lobby(players.as_slice())).await?;
+    let message = handle.message().await?;
+
+    while let Some(interaction) = message
+        .await_component_interaction(ctx.serenity_context())
+        .timeout(deadline.saturating_duration_since(Instant::now()))
+        .await
+    {
+        let user = interaction.user.id;
+        if interaction.data.custom_id == deal_id && user == starter {
+            interaction.defer(ctx.serenity_context()).await?;
+            break;
+        }
+
+        let joined = if interaction.data.custom_id == deal_id {
+            Err(eyre!("Only {} can deal", starter.mention()))
+        } else if players.contains(&user) {
+            Err(eyre!("You already joined"))
+        } else if players.len() >= MAX_PLAYERS {
+            Err(eyre!("The table is full"))
+        } else {
+            ctx.data().with_mut(|cfg| cfg.place_wager(cur, user, wager, "Blackjack")).await
+        };
+        match joined {
+            Ok(()) => {
+                players.push(user);
+                lobby(players.as_slice()).update_to_component(ctx.serenity_context(), &interaction).await?;
+            }
+            Err(e) => {
+                let reply = CreateReply::new().content(e.to_string()).ephemeral(true);
+                reply.respond_to_component(ctx.serenity_context(), &interaction).await?;
+            }
+        }
+    }
+
+    let state: &StateT = ctx.data().state();
+    let (rng, fairness) = state.next_game(starter);
+    state.save_seeds(ctx.guild_id().some()?).await?;
+    let mut game = Game::deal(wager, players.clone(), rng, fairness)?;
+
+    let buttons = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(hit_id).label("Hit").style(ButtonStyle::Primary),
+        CreateButton::new(stand_id).label("Stand").style(ButtonStyle::Secondary),
+    ])];
+    handle.edit(ctx, game.reply(cur, None).components(buttons.clone())).await?;
+
+    while !game.finished() {
+        let Some(interaction) = message.await_component_interaction(ctx.serenity_context()).timeout(TURN_TIME).await
+        else {
+            // players who don't act stand
+            game.hands.iter_mut().for_each(|hand| hand.standing = true);
+            break;
+        };
+
+        let Some(i) = game.hands.iter().position(|hand| hand.user == interaction.user.id && !hand.done()) else {
+            let reply = CreateReply::new().content("You don't have a hand to play").ephemeral(true);
+            reply.respond_to_component(ctx.serenity_context(), &interaction).await?;
+            continue;
+        };
+        if interaction.data.custom_id == hit_id {
+            let card = game.draw()?;
+            game.hands[i].cards.push(card);
+        } else {
+            game.hands[i].standing = true;
+        }
+
+        let components = if game.finished() { vec![] } else { buttons.clone() };
+        game.reply(cur, None).components(components).update_to_component(ctx.serenity_context(), &interaction).await?;
+    }
+
+    game.play_dealer()?;
+    Ok((handle, game))
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Card(u8);
+
+impl Card {
+    fn is_ace(self) -> bool {
+        self.0 % 13 == 0
+    }
+
+    /// Aces count as 11 here, see [`value`]
+    fn value(self) -> u32 {
+        match self.0 % 13 {
+            0 => 11,
+            rank @ 1..=8 => u32::from(rank) + 1,
+            _ => 10,
+        }
+    }
+}
+
+impl Display for Card {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", RANKS[usize::from(self.0 % 13)], SUITS[usize::from(self.0 / 13)])
+    }
+}
+
+/// Best value of the cards, aces count as 1 if 11 would bust
+fn value(cards: &[Card]) -> u32 {
+    let mut total = cards.iter().map(|c| c.value()).sum::<u32>();
+    let mut aces = cards.iter().filter(|c| c.is_ace()).count();
+    while total > 21 && aces > 0 {
+        total -= 10;
+        aces -= 1;
+    }
+    total
+}
+
+fn is_blackjack(cards: &[Card]) -> bool {
+    cards.len() == 2 && value(cards) == 21
+}
+
+struct Hand {
+    user: UserId,
+    cards: Vec<Card>,
+    standing: bool,
+}
+
+impl Hand {
+    fn done(&self) -> bool {
+        self.standing || value(&self.cards) >= 21
+    }
+}
+
+struct Game {
+    wager: u64,
+    deck: Vec<Card>,
+    dealer: Vec<Card>,
+    hands: Vec<Hand>,
+    fairness: String,
+}
+
+impl Game {
+    fn deal(wager: u64, players: Vec<UserId>, mut rng: FairRng, fairness: String) -> Result<Self> {
+        let mut deck = (0..52 * DECKS).map(|i| Card((i % 52) as u8)).collect_vec();
+        for i in (1..deck.len()).rev() {
+            deck.swap(i, rng.below(i as u64 + 1) as usize);
+        }
+
+        let hands = players.into_iter().map(|user| Hand { user, cards: vec![], standing: false }).collect();
+        let mut game = Game { wager, deck, dealer: vec![], hands, fairness };
+        for _ in 0..2 {
+            for i in 0..game.hands.len() {
+                let card = game.draw()?;
+                game.hands[i].cards.push(card);
+            }
+            let card = game.draw()?;
+            game.dealer.push(card);
+        }
+
+        // nothing left to play if the dealer has blackjack
+        if is_blackjack(&game.dealer) {
+            game.hands.iter_mut().for_each(|hand| hand.standing = true);
+        }
+        Ok(game)
+    }
+
+    fn draw(&mut self) -> Result<Card> {
+        self.deck.pop().ok_or_eyre("The shoe is empty")
+    }
+
+    fn finished(&self) -> bool {
+        self.hands.iter().all(Hand::done)
+    }
+
+    /// The dealer draws to 17, but only if any player hasn't busted
+    fn play_dealer(&mut self) -> Result<()> {
+        if self.hands.iter().any(|hand| value(&hand.cards) <= 21) {
+            while value(&self.dealer) < 17 {
+                let card = self.draw()?;
+                self.dealer.push(card);
+            }
+        }
+        Ok(())
+    }
+
+    /// What the hand pays including the wager, the house edge is taken from the winnings
+    fn payout(&self, hand: &Hand, keep: f64) -> u64 {
+        let (player, dealer) = (value(&hand.cards), value(&self.dealer));
+        let winnings = if player > 21 || (is_blackjack(&self.dealer) && !is_blackjack(&hand.cards)) {
+            return 0;
+        } else if is_blackjack(&hand.cards) && !is_blackjack(&self.dealer) {
+            self.wager as f64 * 1.5
+        } else if dealer > 21 || player > dealer {
+            self.wager as f64
+        } else if player == dealer {
+            return self.wager;
+        } else {
+            return 0;
+        };
+        self.wager + (winnings * keep).floor() as u64
+    }
+
+    fn reply(&self, cur: &Currency, payouts: Option<&[(UserId, u64)]>) -> CreateReply {
+        let fmt_cards = |cards: &[Card]| format!("{} ({})", cards.iter().join(" "), value(cards));
+
+        let dealer = match payouts {
+            Some(_) => fmt_cards(&self.dealer),
+            None => format!("{} 🂠", self.dealer[0]),
+        };
+        let hands = self
+            .hands
+            .iter()
+            .enumerate()
+            .map(|(i, hand)| {
+                let status = match payouts {
+                    Some(payouts) => format!(" → {}", cur.fmt(payouts[i].1)),
+                    None if value(&hand.cards) > 21 => " 💥".to_string(),
+                    None if is_blackjack(&hand.cards) => " ⭐".to_string(),
+                    None if hand.done() => " ✋".to_string(),
+                    None => String::new(),
+                };
+                format!("{}: {}{status}", hand.user.mention(), fmt_cards(&hand.cards))
+            })
+            .join("\n");
+
+        let embed = CreateEmbed::new()
+            .title("🃏 Blackjack")
+            .description(format!("Dealer: {dealer}\n\n{hands}"))
+            .field("Wager", cur.fmt(self.wager), true)
+            .footer(CreateEmbedFooter::new(&self.fairness))
+            .colour(if payouts.is_some() { Colour::DARKER_GREY } else { Colour::BLITZ_BLUE });
+        CreateReply::new().embed(embed)
+    }
+}
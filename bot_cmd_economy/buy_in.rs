@@ -1,5 +1,8 @@
+use crate::ledger::TransactionKind;
 use crate::{ConfigT, Currency, GamblingTable, StateT};
+use bot_core::ext::option::OptionExt as _;
 use bot_core::{EvtContext, State, With};
+use chrono::Utc;
 use eyre::{OptionExt, Result, ensure};
 use poise::serenity_prelude::{ComponentInteraction, UserId};
 use uuid::Uuid;
@@ -36,23 +39,21 @@ fn buy_in(
     table_id: Uuid,
     user_id: UserId,
 ) -> std::result::Result<GamblingTable, eyre::Error> {
-    let table = cfg.gambling_tables.get_mut(&table_id).ok_or_eyre("Table doesn't exist")?;
+    let table = cfg.gambling_tables.get(&table_id).ok_or_eyre("Table doesn't exist")?;
+    let (buyin, name) = (table.buyin, table.name.clone());
 
     // remove money from player's account
-    let account = cfg.account.entry(user_id).or_default();
-    ensure!(
-        account.balance >= table.buyin,
-        "You don't have enough money for a buy-in: {} < {}",
-        cur.fmt(account.balance),
-        cur.fmt(table.buyin),
-    );
-    account.balance -= table.buyin;
+    let balance = cfg.account.get(&user_id).map_or(0, |a| a.balance);
+    ensure!(balance >= buyin, "You don't have enough money for a buy-in: {} < {}", cur.fmt(balance), cur.fmt(buyin),);
+    cfg.transact(user_id, -i64::try_from(buyin)?, TransactionKind::BuyIn { table: table_id, name })?;
 
     // add money to table
+    let table = cfg.gambling_tables.get_mut(&table_id).some()?;
     let bet = table.players.entry(user_id).or_default();
     *bet += table.buyin;
     tracing::info!("User {user_id} bought in for {} on table {}", cur.fmt(table.buyin), table_id);
     table.pot += table.buyin;
+    table.last_activity = Utc::now();
 
     Ok(table.clone())
 }
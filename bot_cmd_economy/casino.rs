@@ -0,0 +1,391 @@
+//! Games run by the bot instead of a dealer.
+//! The results are provably fair: each one is derived from `sha256("{server}:{client}:{nonce}:{round}")`,
+//! where only the hash of the server seed is known while playing and `/fairness` reveals the seed afterwards.
+
+use crate::ledger::TransactionKind;
+use crate::{ConfigT, Currency, StateT};
+use bot_core::ext::option::OptionExt as _;
+use bot_core::{CmdContext, State, With};
+use eyre::{Result, WrapErr as _, bail, ensure};
+use poise::serenity_prelude::{Colour, CreateEmbed, CreateEmbedFooter, GuildId, UserId};
+use poise::{ChoiceParameter, CreateReply};
+use sha2::{Digest as _, Sha256};
+use std::collections::BTreeMap;
+use tokio::{fs, io};
+
+const RED_NUMBERS: [u64; 18] = [1, 3, 5, 7, 9, 12, 14, 16, 18, 19, 21, 23, 25, 27, 30, 32, 34, 36];
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, sensible::Default)]
+pub(crate) struct CasinoConfig {
+    /// Share of each wager the house keeps on average, in percent
+    #[default(2.0)]
+    house_edge_percent: f64,
+    #[default(1)]
+    min_wager: u64,
+    max_wager: Option<u64>,
+}
+
+impl CasinoConfig {
+    /// What winning a bet with the given chance pays, including the wager
+    pub(crate) fn payout(&self, wager: u64, chance: f64) -> u64 {
+        (wager as f64 / chance * self.keep()).floor() as u64
+    }
+
+    /// Share of winnings the player keeps
+    pub(crate) fn keep(&self) -> f64 {
+        1.0 - self.house_edge_percent / 100.0
+    }
+}
+
+/// The seeds a player's games are derived from
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Seeds {
+    /// Only its hash is shown until it's revealed and replaced
+    server: String,
+    /// Chosen by the player
+    client: String,
+    /// Number of games played with these seeds
+    nonce: u64,
+}
+
+impl Seeds {
+    fn new(client: Option<String>) -> Self {
+        Seeds {
+            server: hex::encode(rand::random::<[u8; 32]>()),
+            client: client.unwrap_or_else(|| hex::encode(rand::random::<[u8; 8]>())),
+            nonce: 0,
+        }
+    }
+
+    fn commitment(&self) -> String {
+        hex::encode(Sha256::digest(self.server.as_bytes()))
+    }
+}
+
+/// Randomness of a single game
+pub(crate) struct FairRng {
+    seed: String,
+    round: u64,
+}
+
+impl FairRng {
+    /// A number in `0..n`
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        let hash = Sha256::digest(format!("{}:{}", self.seed, self.round));
+        self.round += 1;
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&hash[..8]);
+        ((u128::from(u64::from_be_bytes(bytes)) * u128::from(n)) >> 64) as u64
+    }
+}
+
+impl ConfigT {
+    /// Takes the wager from the player's account
+    pub(crate) fn place_wager(&mut self, cur: &Currency, user: UserId, wager: u64, game: &str) -> Result<()> {
+        ensure!(wager >= self.casino.min_wager, "The minimum wager is {}", cur.fmt(self.casino.min_wager));
+        if let Some(max) = self.casino.max_wager {
+            ensure!(wager <= max, "The maximum wager is {}", cur.fmt(max));
+        }
+        let balance = self.account.get(&user).map_or(0, |a| a.balance);
+        ensure!(balance >= wager, "You don't have enough money: {} < {}", cur.fmt(balance), cur.fmt(wager));
+        self.transact(user, -i64::try_from(wager)?, TransactionKind::Wager { game: game.to_string() })?;
+        Ok(())
+    }
+
+    pub(crate) fn pay_winnings(&mut self, user: UserId, amount: u64, game: &str) -> Result<()> {
+        if amount != 0 {
+            self.transact(user, amount.try_into()?, TransactionKind::Winnings { game: game.to_string() })?;
+        }
+        Ok(())
+    }
+}
+
+impl StateT {
+    /// Randomness for the player's next game and a footer that lets them verify it later
+    pub(crate) fn next_game(&self, user: UserId) -> (FairRng, String) {
+        let mut seeds = self.seeds.entry(user).or_insert_with(|| Seeds::new(None));
+        let rng = FairRng { seed: format!("{}:{}:{}", seeds.server, seeds.client, seeds.nonce), round: 0 };
+        let footer = format!("Seed {}… · Nonce {}", &seeds.commitment()[..16], seeds.nonce);
+        seeds.nonce += 1;
+        (rng, footer)
+    }
+
+    /// Restores the seeds saved before the last restart
+    pub(crate) async fn load_seeds(&self, guild_id: GuildId) -> Result<()> {
+        let yaml = match fs::read_to_string(seeds_file(guild_id)).await {
+            Ok(yaml) => yaml,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).wrap_err("Failed to read the casino seeds"),
+        };
+        let seeds: BTreeMap<UserId, Seeds> = serde_yaml_ng::from_str(&yaml)?;
+        for (user, seeds) in seeds {
+            self.seeds.insert(user, seeds);
+        }
+        Ok(())
+    }
+
+    /// Saves the seeds, otherwise a restart would lose them before they're revealed
+    pub(crate) async fn save_seeds(&self, guild_id: GuildId) -> Result<()> {
+        let _lock = self.seeds_lock.lock().await;
+        let seeds: BTreeMap<UserId, Seeds> = self.seeds.iter().map(|e| (*e.key(), e.value().clone())).collect();
+        let path = seeds_file(guild_id);
+        let temp = format!("{path}.tmp");
+        fs::write(&temp, serde_yaml_ng::to_string(&seeds)?).await?;
+        fs::rename(temp, path).await.wrap_err("Failed to save the casino seeds")
+    }
+}
+
+/// Kept next to the bot instead of in the config, which others can read
+fn seeds_file(guild_id: GuildId) -> String {
+    format!("casino_seeds_{guild_id}.yaml")
+}
+
+pub(crate) fn result_embed(
+    cur: &Currency,
+    title: &str,
+    result: String,
+    wager: u64,
+    payout: u64,
+    fairness: String,
+) -> CreateEmbed {
+    CreateEmbed::new()
+        .title(title)
+        .description(result)
+        .field("Wager", cur.fmt(wager), true)
+        .field("Payout", cur.fmt(payout), true)
+        .footer(CreateEmbedFooter::new(fairness))
+        .colour(if payout > wager {
+            Colour::DARK_GREEN
+        } else if payout == wager {
+            Colour::GOLD
+        } else {
+            Colour::RED
+        })
+}
+
+#[derive(ChoiceParameter, Clone, Copy, Debug, PartialEq)]
+pub enum CoinSide {
+    Heads,
+    Tails,
+}
+
+/// Flip a coin, doubles your wager minus the house edge
+#[poise::command(slash_command, guild_only)]
+pub async fn coinflip<D: With<ConfigT> + State<StateT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "How much to bet"] wager: u64,
+    #[description = "The side you bet on"] side: CoinSide,
+) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let user = ctx.author().id;
+    let state: &StateT = ctx.data().state();
+    let (flip, payout, fairness) = ctx
+        .data()
+        .with_mut(|cfg| {
+            cfg.place_wager(&cur, user, wager, "Coinflip")?;
+            let (mut rng, fairness) = state.next_game(user);
+            let flip = if rng.below(2) == 0 { CoinSide::Heads } else { CoinSide::Tails };
+            let payout = if flip == side { cfg.casino.payout(wager, 0.5) } else { 0 };
+            cfg.pay_winnings(user, payout, "Coinflip")?;
+            Ok((flip, payout, fairness))
+        })
+        .await?;
+    state.save_seeds(ctx.guild_id().some()?).await?;
+
+    let result = format!("🪙 **{}**", flip.name());
+    ctx.send(CreateReply::new().embed(result_embed(&cur, "Coinflip", result, wager, payout, fairness))).await?;
+
+    Ok(())
+}
+
+/// Roll a number from 1 to 100 and win if it's below your target
+#[poise::command(slash_command, guild_only)]
+pub async fn dice<D: With<ConfigT> + State<StateT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "How much to bet"] wager: u64,
+    #[description = "The roll has to be below this, lower targets pay more"]
+    #[min = 2]
+    #[max = 100]
+    under: u64,
+) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let user = ctx.author().id;
+    let state: &StateT = ctx.data().state();
+    let (roll, payout, fairness) = ctx
+        .data()
+        .with_mut(|cfg| {
+            cfg.place_wager(&cur, user, wager, "Dice")?;
+            let (mut rng, fairness) = state.next_game(user);
+            let roll = rng.below(100) + 1;
+            let payout = if roll < under { cfg.casino.payout(wager, (under - 1) as f64 / 100.0) } else { 0 };
+            cfg.pay_winnings(user, payout, "Dice")?;
+            Ok((roll, payout, fairness))
+        })
+        .await?;
+    state.save_seeds(ctx.guild_id().some()?).await?;
+
+    let result = format!("🎲 **{roll}** (under {under})");
+    ctx.send(CreateReply::new().embed(result_embed(&cur, "Dice", result, wager, payout, fairness))).await?;
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RouletteBet {
+    Number(u64),
+    Red,
+    Black,
+    Odd,
+    Even,
+    Low,
+    High,
+    Dozen(u64),
+}
+
+impl RouletteBet {
+    fn parse(input: &str) -> Result<Self> {
+        Ok(match input.trim().to_lowercase().as_str() {
+            "red" => RouletteBet::Red,
+            "black" => RouletteBet::Black,
+            "odd" => RouletteBet::Odd,
+            "even" => RouletteBet::Even,
+            "low" | "1-18" => RouletteBet::Low,
+            "high" | "19-36" => RouletteBet::High,
+            "1st" | "1-12" => RouletteBet::Dozen(0),
+            "2nd" | "13-24" => RouletteBet::Dozen(1),
+            "3rd" | "25-36" => RouletteBet::Dozen(2),
+            number => match number.parse() {
+                Ok(n) if n <= 36 => RouletteBet::Number(n),
+                _ => bail!("Bet on a number from 0 to 36, red, black, odd, even, low, high, 1st, 2nd or 3rd"),
+            },
+        })
+    }
+
+    fn wins(self, pocket: u64) -> bool {
+        match self {
+            RouletteBet::Number(n) => pocket == n,
+            // zero is neither red, black, odd, even, low nor high
+            _ if pocket == 0 => false,
+            RouletteBet::Red => RED_NUMBERS.contains(&pocket),
+            RouletteBet::Black => !RED_NUMBERS.contains(&pocket),
+            RouletteBet::Odd => pocket % 2 == 1,
+            RouletteBet::Even => pocket % 2 == 0,
+            RouletteBet::Low => pocket <= 18,
+            RouletteBet::High => pocket > 18,
+            RouletteBet::Dozen(dozen) => (pocket - 1) / 12 == dozen,
+        }
+    }
+
+    fn chance(self) -> f64 {
+        (0..=36).filter(|&pocket| self.wins(pocket)).count() as f64 / 37.0
+    }
+}
+
+/// Spin a roulette wheel with a single zero
+#[poise::command(slash_command, guild_only)]
+pub async fn roulette<D: With<ConfigT> + State<StateT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "How much to bet"] wager: u64,
+    #[description = "A number, red, black, odd, even, low, high, 1st, 2nd or 3rd (dozen)"] bet: String,
+) -> Result<()> {
+    let bet = RouletteBet::parse(&bet)?;
+    let cur = Currency::read(ctx.data()).await?;
+    let user = ctx.author().id;
+    let state: &StateT = ctx.data().state();
+    let (pocket, payout, fairness) = ctx
+        .data()
+        .with_mut(|cfg| {
+            cfg.place_wager(&cur, user, wager, "Roulette")?;
+            let (mut rng, fairness) = state.next_game(user);
+            let pocket = rng.below(37);
+            let payout = if bet.wins(pocket) { cfg.casino.payout(wager, bet.chance()) } else { 0 };
+            cfg.pay_winnings(user, payout, "Roulette")?;
+            Ok((pocket, payout, fairness))
+        })
+        .await?;
+    state.save_seeds(ctx.guild_id().some()?).await?;
+
+    let colour = match pocket {
+        0 => "🟩",
+        p if RED_NUMBERS.contains(&p) => "🟥",
+        _ => "⬛",
+    };
+    let result = format!("{colour} **{pocket}**");
+    ctx.send(CreateReply::new().embed(result_embed(&cur, "Roulette", result, wager, payout, fairness))).await?;
+
+    Ok(())
+}
+
+/// Reveal the seed of your past games and start using a new one
+#[poise::command(slash_command, guild_only)]
+pub async fn fairness<D: With<ConfigT> + State<StateT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "Your own seed to mix into future games"] client_seed: Option<String>,
+) -> Result<()> {
+    let user = ctx.author().id;
+    let state: &StateT = ctx.data().state();
+    let old = state.seeds.remove(&user).map(|(_, seeds)| seeds);
+    let client = client_seed.or_else(|| old.as_ref().map(|seeds| seeds.client.clone()));
+    let new = state.seeds.entry(user).or_insert(Seeds::new(client)).clone();
+    state.save_seeds(ctx.guild_id().some()?).await?;
+    // only revealed seeds are stored, the config can be read by others
+    let old = ctx
+        .data()
+        .with_mut_ok(|cfg| match old {
+            Some(old) => {
+                cfg.revealed_seeds.insert(user, old.clone());
+                Some(old)
+            }
+            None => cfg.revealed_seeds.get(&user).cloned(),
+        })
+        .await?;
+
+    let mut embed = CreateEmbed::new()
+        .title("Fairness")
+        .description(
+            "Each result comes from `sha256(\"{server seed}:{client seed}:{nonce}:{round}\")`: \
+             the first 8 bytes as a big-endian number `x` give `x * n / 2^64` for `n` possible outcomes.",
+        )
+        .colour(Colour::BLITZ_BLUE);
+    if let Some(old) = old {
+        embed = embed.field(
+            "Revealed seeds",
+            format!(
+                "Server: `{}`\nHash: `{}`\nClient: `{}`\nGames: {}",
+                old.server,
+                old.commitment(),
+                old.client,
+                old.nonce
+            ),
+            false,
+        );
+    }
+    embed = embed.field("New seeds", format!("Hash: `{}`\nClient: `{}`", new.commitment(), new.client), false);
+    ctx.send(CreateReply::new().embed(embed).ephemeral(true)).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roulette_odds() {
+        assert_eq!(RouletteBet::parse("red").unwrap().chance(), 18.0 / 37.0);
+        assert_eq!(RouletteBet::parse("2nd").unwrap().chance(), 12.0 / 37.0);
+        assert_eq!(RouletteBet::parse("0").unwrap().chance(), 1.0 / 37.0);
+        assert!(RouletteBet::parse("37").is_err());
+        assert!(!RouletteBet::Even.wins(0));
+    }
+
+    #[test]
+    fn fair_rng_is_reproducible() {
+        let rolls = || {
+            let mut rng = FairRng { seed: "server:client:0".to_string(), round: 0 };
+            (0..100).map(|_| rng.below(6)).collect::<Vec<_>>()
+        };
+        assert_eq!(rolls(), rolls());
+        assert!(rolls().iter().all(|&roll| roll < 6));
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{ConfigT, Currency, GamblingTable};
 use bot_core::ext::option::OptionExt as _;
 use bot_core::{CmdContext, With};
+use chrono::Utc;
 use eyre::Result;
 use uuid::Uuid;
 
@@ -26,13 +27,23 @@ pub async fn gamble<D: With<ConfigT>>(
         dealer: ctx.author().id,
         players: Default::default(),
         pot: 0,
+        last_activity: Utc::now(),
+        message: None,
     };
 
     let reply = table.reply(&cur, id);
 
     ctx.data().with_mut_ok(|cfg| cfg.gambling_tables.insert(id, table)).await?;
 
-    ctx.send(reply).await?;
+    let handle = ctx.send(reply).await?;
+    let message = handle.message().await?;
+    ctx.data()
+        .with_mut_ok(|cfg| {
+            if let Some(table) = cfg.gambling_tables.get_mut(&id) {
+                table.message = Some((message.channel_id, message.id));
+            }
+        })
+        .await?;
 
     Ok(())
 }
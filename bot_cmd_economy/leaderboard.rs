@@ -1,31 +1,176 @@
+use crate::ledger::TransactionKind;
 use crate::{ConfigT, Currency};
+use bot_core::ext::create_reply::CreateReplyExt;
 use bot_core::{CmdContext, With};
-use eyre::Result;
+use chrono::{DateTime, TimeDelta, Utc};
+use eyre::{Result, ensure};
 use itertools::Itertools;
-use poise::CreateReply;
-use poise::serenity_prelude::{Colour, CreateEmbed, Mentionable};
+use poise::serenity_prelude::{
+    ButtonStyle, Colour, CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, Mentionable, UserId,
+};
+use poise::{ChoiceParameter, CreateReply};
 use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use uuid::Uuid;
+
+const PAGE_SIZE: usize = 15;
+
+#[derive(ChoiceParameter, Clone, Copy, Debug, PartialEq)]
+pub enum LeaderboardStat {
+    Balance,
+    #[name = "Gambling winnings"]
+    Gambling,
+    #[name = "Biggest win"]
+    BiggestWin,
+    Income,
+}
+
+impl ConfigT {
+    /// Members ranked by the stat, only counting transactions since the given time
+    fn ranking(&self, stat: LeaderboardStat, since: DateTime<Utc>) -> Vec<(UserId, i64)> {
+        let mut values = BTreeMap::<UserId, i64>::new();
+        let transactions = || self.ledger.iter().filter(|t| t.time >= since);
+        match stat {
+            LeaderboardStat::Balance => {
+                for (&user, account) in self.account.iter().filter(|(user, _)| Some(**user) != self.policy.bank) {
+                    values.insert(user, i64::try_from(account.balance).unwrap_or(i64::MAX));
+                }
+            }
+            LeaderboardStat::Gambling => {
+                for t in transactions().filter(|t| t.kind.is_gambling()) {
+                    *values.entry(t.user).or_default() += t.amount;
+                }
+            }
+            LeaderboardStat::BiggestWin => {
+                for (_, user, win) in self.wins().into_iter().filter(|&(time, _, win)| time >= since && win > 0) {
+                    let biggest = values.entry(user).or_insert(win);
+                    *biggest = (*biggest).max(win);
+                }
+            }
+            LeaderboardStat::Income => {
+                for t in transactions().filter(|t| t.kind.is_income()) {
+                    *values.entry(t.user).or_default() += t.amount;
+                }
+            }
+        }
+        values.into_iter().sorted_by_key(|&(user, value)| (Reverse(value), user)).collect()
+    }
+
+    /// Profit of every gambling payout, i.e. the payout minus what the member put in for it
+    fn wins(&self) -> Vec<(DateTime<Utc>, UserId, i64)> {
+        let mut put_in = HashMap::<(UserId, Uuid), i64>::new();
+        let mut wagers = HashMap::<(UserId, &str), i64>::new();
+        let mut wins = vec![];
+        for t in &self.ledger {
+            match &t.kind {
+                TransactionKind::BuyIn { table: pool, .. } | TransactionKind::Stake { market: pool, .. } => {
+                    *put_in.entry((t.user, *pool)).or_default() -= t.amount;
+                }
+                TransactionKind::Wager { game } => {
+                    // a lost wager has no winnings, so only the latest one is paid out next
+                    wagers.insert((t.user, game.as_str()), -t.amount);
+                }
+                TransactionKind::Payout { table: pool, .. } | TransactionKind::MarketPayout { market: pool, .. } => {
+                    let cost = put_in.remove(&(t.user, *pool)).unwrap_or_default();
+                    wins.push((t.time, t.user, t.amount - cost));
+                }
+                TransactionKind::Winnings { game } => {
+                    let cost = wagers.remove(&(t.user, game.as_str())).unwrap_or_default();
+                    wins.push((t.time, t.user, t.amount - cost));
+                }
+                _ => {}
+            }
+        }
+        wins
+    }
+}
 
 /// Check the economy leaderboard
 #[poise::command(slash_command, guild_only)]
-pub async fn leaderboard<D: With<ConfigT>>(ctx: CmdContext<'_, D>) -> Result<()> {
+pub async fn leaderboard<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "What to rank by, defaults to balance"] stat: Option<LeaderboardStat>,
+    #[description = "Only count the last days"]
+    #[min = 1]
+    days: Option<u32>,
+) -> Result<()> {
     let cur = Currency::read(ctx.data()).await?;
-    let mut accounts =
-        ctx.data().with_ok(|cfg| cfg.account.iter().map(|(id, account)| (*id, account.clone())).collect_vec()).await?;
+    let stat = stat.unwrap_or(LeaderboardStat::Balance);
+    ensure!(days.is_none() || stat != LeaderboardStat::Balance, "Balances can't be limited to the last days");
 
-    accounts.sort_by_key(|(_, account)| Reverse(account.balance));
+    let since = match days {
+        Some(days) => Utc::now() - TimeDelta::days(days.into()),
+        None => DateTime::<Utc>::MIN_UTC,
+    };
+    let ranking = ctx.data().with_ok(|cfg| cfg.ranking(stat, since)).await?;
+    ensure!(!ranking.is_empty(), "Nobody is on this leaderboard yet");
 
-    let leaderboard = accounts
-        .iter()
-        .enumerate()
-        .map(|(i, (id, u))| format!("{} {} {}", placement(i + 1), id.mention(), cur.fmt(u.balance)))
-        .join("\n");
+    let caller = ctx.author().id;
+    let caller_rank = ranking.iter().position(|&(user, _)| user == caller);
+    let fmt_value = |value: i64| match stat {
+        LeaderboardStat::Gambling => cur.fmt_signed(value),
+        _ => cur.fmt(value.unsigned_abs()),
+    };
+    let fmt_line = |i: usize| {
+        let (user, value) = ranking[i];
+        let line = format!("{} {} {}", placement(i + 1), user.mention(), fmt_value(value));
+        if user == caller { format!("**{line}**") } else { line }
+    };
 
-    let embed = CreateEmbed::new().title("Leaderboard").description(leaderboard).colour(Colour::DARK_GOLD);
+    let title = match days {
+        Some(days) => format!("Leaderboard: {} (last {days} days)", stat.name()),
+        None => format!("Leaderboard: {}", stat.name()),
+    };
+    let pages = ranking.len().div_ceil(PAGE_SIZE);
+    let prev_id = "~economy.leaderboard_prev";
+    let next_id = "~economy.leaderboard_next";
+    let reply = |page: usize, active: bool| {
+        let shown = page * PAGE_SIZE..((page + 1) * PAGE_SIZE).min(ranking.len());
+        let mut lines = shown.clone().map(fmt_line).join("\n");
+        // always show where the caller stands
+        if let Some(rank) = caller_rank.filter(|rank| !shown.contains(rank)) {
+            lines += &format!("\n…\n{}", fmt_line(rank));
+        }
+        let footer = match caller_rank {
+            Some(rank) => format!("Page {}/{pages} · You're #{}", page + 1, rank + 1),
+            None => format!("Page {}/{pages} · You're not ranked", page + 1),
+        };
+        let embed = CreateEmbed::new()
+            .title(&title)
+            .description(lines)
+            .footer(CreateEmbedFooter::new(footer))
+            .colour(if active { Colour::DARK_GOLD } else { Colour::DARKER_GREY });
+        let buttons = vec![
+            CreateButton::new(prev_id).emoji('◀').style(ButtonStyle::Secondary).disabled(page == 0),
+            CreateButton::new(next_id).emoji('▶').style(ButtonStyle::Secondary).disabled(page + 1 >= pages),
+        ];
+        CreateReply::new().embed(embed).components(if active && pages > 1 {
+            vec![CreateActionRow::Buttons(buttons)]
+        } else {
+            vec![]
+        })
+    };
 
-    let reply = CreateReply::new().embed(embed);
+    let handle = ctx.send(reply(0, true)).await?;
+    if pages == 1 {
+        return Ok(());
+    }
+    let message = handle.message().await?;
+
+    let mut page = 0;
+    while let Some(interaction) = message
+        .await_component_interaction(ctx.serenity_context())
+        .author_id(caller)
+        .timeout(Duration::from_secs(5 * 60))
+        .await
+    {
+        page = if interaction.data.custom_id == prev_id { page.saturating_sub(1) } else { (page + 1).min(pages - 1) };
+        reply(page, true).update_to_component(ctx.serenity_context(), &interaction).await?;
+    }
+
+    handle.edit(ctx, reply(page, false)).await?;
 
-    ctx.send(reply).await?;
     Ok(())
 }
 
@@ -37,3 +182,29 @@ fn placement(n: usize) -> String {
         _ => format!("{n}."),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ledger::Transaction;
+
+    #[test]
+    fn biggest_win_subtracts_the_stake() {
+        let (a, b) = (UserId::new(1), UserId::new(2));
+        let (table, game) = (Uuid::new_v4(), "Dice".to_string());
+        let mut cfg = ConfigT::default();
+        let mut push = |user, amount, kind| cfg.ledger.push(Transaction { time: Utc::now(), user, amount, kind });
+        push(a, -50, TransactionKind::BuyIn { table, name: "Table".to_string() });
+        push(b, -50, TransactionKind::BuyIn { table, name: "Table".to_string() });
+        push(a, 100, TransactionKind::Payout { table, name: "Table".to_string() });
+        push(b, -10, TransactionKind::Wager { game: game.clone() });
+        push(b, -20, TransactionKind::Wager { game: game.clone() });
+        push(b, 70, TransactionKind::Winnings { game });
+
+        let ranking = cfg.ranking(LeaderboardStat::BiggestWin, DateTime::<Utc>::MIN_UTC);
+        // ties are ordered by user
+        assert_eq!(ranking, [(a, 50), (b, 50)]);
+        let ranking = cfg.ranking(LeaderboardStat::Gambling, DateTime::<Utc>::MIN_UTC);
+        assert_eq!(ranking, [(a, 50), (b, -10)]);
+    }
+}
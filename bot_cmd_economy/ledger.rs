@@ -0,0 +1,323 @@
+use crate::{ConfigT, Currency};
+use bot_core::With;
+use chrono::{DateTime, TimeDelta, Utc};
+use eyre::{OptionExt as _, Result};
+use itertools::Itertools as _;
+use poise::serenity_prelude::{Mentionable as _, UserId};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+pub(crate) fn default_retention() -> TimeDelta {
+    TimeDelta::days(180)
+}
+
+/// A change of a single account's balance
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Transaction {
+    pub(crate) time: DateTime<Utc>,
+    pub(crate) user: UserId,
+    /// Credited if positive, debited if negative
+    pub(crate) amount: i64,
+    pub(crate) kind: TransactionKind,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum TransactionKind {
+    /// Makes the ledger match a balance that changed without a transaction, e.g. before the ledger existed
+    Adjustment,
+    Income {
+        days: u32,
+    },
+    BuyIn {
+        table: Uuid,
+        name: String,
+    },
+    Payout {
+        table: Uuid,
+        name: String,
+    },
+    /// Bet returned when a table was closed without a payout
+    TableRefund {
+        table: Uuid,
+        name: String,
+    },
+    /// The dealer's share of a table's payouts
+    Commission {
+        table: Uuid,
+        name: String,
+    },
+    /// Money sent to or received from another member
+    Transfer {
+        user: UserId,
+        note: Option<String>,
+    },
+    TransferFee,
+    /// Bet on a game run by the bot
+    Wager {
+        game: String,
+    },
+    Winnings {
+        game: String,
+    },
+    Stake {
+        market: Uuid,
+        question: String,
+    },
+    MarketPayout {
+        market: Uuid,
+        question: String,
+    },
+    MarketRefund {
+        market: Uuid,
+        question: String,
+    },
+    Purchase {
+        item: String,
+    },
+    /// Returns the price of a purchase that couldn't be granted
+    Refund {
+        item: String,
+    },
+    /// Earned from messages and voice time, one entry per member and day
+    Activity,
+    /// Taken from the part of the balance above the threshold every period
+    WealthTax,
+    /// Taken from accounts that haven't been used for a while
+    InactivityDecay,
+    /// A fee, tax or decay the bank collected from a member
+    Collected {
+        user: UserId,
+        what: String,
+    },
+    /// Money lent to or borrowed from another member
+    Loan {
+        user: UserId,
+    },
+    LoanRepayment {
+        user: UserId,
+    },
+}
+
+impl Transaction {
+    pub(crate) fn describe(&self) -> String {
+        match &self.kind {
+            TransactionKind::Adjustment => "Adjustment".to_string(),
+            TransactionKind::Income { days } => format!("Income for {days} day{}", if *days == 1 { "" } else { "s" }),
+            TransactionKind::BuyIn { name, .. } => format!("Buy-in at {name}"),
+            TransactionKind::Payout { name, .. } => format!("Payout from {name}"),
+            TransactionKind::TableRefund { name, .. } => format!("Refund from {name}"),
+            TransactionKind::Commission { name, .. } => format!("Commission from {name}"),
+            TransactionKind::Transfer { user, note } => {
+                let direction = if self.amount < 0 { "Sent to" } else { "Received from" };
+                format!("{direction} {}{}", user.mention(), note.as_ref().map(|n| format!(": {n}")).unwrap_or_default())
+            }
+            TransactionKind::TransferFee => "Transfer fee".to_string(),
+            TransactionKind::Wager { game } => format!("Wager on {game}"),
+            TransactionKind::Winnings { game } => format!("Winnings from {game}"),
+            TransactionKind::Stake { question, .. } => format!("Stake on {question}"),
+            TransactionKind::MarketPayout { question, .. } => format!("Payout from {question}"),
+            TransactionKind::MarketRefund { question, .. } => format!("Refund from {question}"),
+            TransactionKind::Purchase { item } => format!("Bought {item}"),
+            TransactionKind::Refund { item } => format!("Refund for {item}"),
+            TransactionKind::Activity => "Activity income".to_string(),
+            TransactionKind::WealthTax => "Wealth tax".to_string(),
+            TransactionKind::InactivityDecay => "Inactivity decay".to_string(),
+            TransactionKind::Collected { user, what } => format!("{what} from {}", user.mention()),
+            TransactionKind::Loan { user } => {
+                let direction = if self.amount < 0 { "Lent to" } else { "Borrowed from" };
+                format!("{direction} {}", user.mention())
+            }
+            TransactionKind::LoanRepayment { user } => {
+                let direction = if self.amount < 0 { "Repaid to" } else { "Repayment from" };
+                format!("{direction} {}", user.mention())
+            }
+        }
+    }
+}
+
+impl TransactionKind {
+    /// The gambling table or market the money went to or came from
+    fn pool(&self) -> Option<Uuid> {
+        match self {
+            TransactionKind::BuyIn { table, .. }
+            | TransactionKind::Payout { table, .. }
+            | TransactionKind::TableRefund { table, .. }
+            | TransactionKind::Commission { table, .. } => Some(*table),
+            TransactionKind::Stake { market, .. }
+            | TransactionKind::MarketPayout { market, .. }
+            | TransactionKind::MarketRefund { market, .. } => Some(*market),
+            _ => None,
+        }
+    }
+
+    /// Money bet or won at tables, games and markets
+    pub(crate) fn is_gambling(&self) -> bool {
+        matches!(
+            self,
+            TransactionKind::BuyIn { .. }
+                | TransactionKind::Payout { .. }
+                | TransactionKind::TableRefund { .. }
+                | TransactionKind::Wager { .. }
+                | TransactionKind::Winnings { .. }
+                | TransactionKind::Stake { .. }
+                | TransactionKind::MarketPayout { .. }
+                | TransactionKind::MarketRefund { .. }
+        )
+    }
+
+    /// Daily income and activity income
+    pub(crate) fn is_income(&self) -> bool {
+        matches!(self, TransactionKind::Income { .. } | TransactionKind::Activity)
+    }
+}
+
+impl ConfigT {
+    /// Changes the user's balance and records it in the ledger, fails if the balance would become negative
+    pub(crate) fn transact(&mut self, user: UserId, amount: i64, kind: TransactionKind) -> Result<u64> {
+        let account = self.account.entry(user).or_default();
+        let balance = account.balance.checked_add_signed(amount).ok_or_eyre("Not enough money")?;
+        account.balance = balance;
+        self.ledger.push(Transaction { time: Utc::now(), user, amount, kind });
+        Ok(balance)
+    }
+
+    /// Whether the transaction is older than the retention. Transactions of open tables and markets are kept for the audit.
+    fn is_old(&self, t: &Transaction, now: DateTime<Utc>) -> bool {
+        let is_open = |pool: Uuid| self.gambling_tables.contains_key(&pool) || self.markets.contains_key(&pool);
+        t.time < now - self.ledger_retention && !t.kind.pool().is_some_and(is_open)
+    }
+
+    /// How many transactions there are to fold, adjustments from earlier compactions don't need folding again
+    fn foldable(&self, now: DateTime<Utc>) -> usize {
+        self.ledger.iter().filter(|&t| self.is_old(t, now) && t.kind != TransactionKind::Adjustment).count()
+    }
+
+    /// Folds the old transactions into one adjustment per account, so that the ledger doesn't grow forever.
+    /// Returns how many were folded.
+    fn compact_ledger(&mut self, now: DateTime<Utc>) -> usize {
+        let folded = self.foldable(now);
+        if folded == 0 {
+            return 0;
+        }
+
+        let (old, kept): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.ledger).into_iter().partition(|t| self.is_old(t, now));
+        let mut sums = BTreeMap::<UserId, (DateTime<Utc>, i64)>::new();
+        for t in old {
+            let (time, sum) = sums.entry(t.user).or_insert((t.time, 0));
+            *time = (*time).max(t.time);
+            *sum += t.amount;
+        }
+        self.ledger = sums
+            .into_iter()
+            .filter(|(_, (_, sum))| *sum != 0)
+            .map(|(user, (time, amount))| Transaction { time, user, amount, kind: TransactionKind::Adjustment })
+            .sorted_by_key(|t| t.time)
+            .chain(kept)
+            .collect();
+        folded
+    }
+
+    /// Accounts, tables and markets whose balance doesn't match the sum of their transactions,
+    /// as (balance, sum of transactions)
+    pub(crate) fn audit(&self) -> Audit {
+        let mut accounts = BTreeMap::<UserId, i128>::new();
+        let mut pools = BTreeMap::<Uuid, i128>::new();
+        for transaction in &self.ledger {
+            *accounts.entry(transaction.user).or_default() += i128::from(transaction.amount);
+            if let Some(pool) = transaction.kind.pool() {
+                // money leaving an account goes to the table or market
+                *pools.entry(pool).or_default() -= i128::from(transaction.amount);
+            }
+        }
+
+        Audit {
+            accounts: self
+                .account
+                .iter()
+                .map(|(&user, account)| (user, (account.balance, accounts.get(&user).copied().unwrap_or_default())))
+                .filter(|(_, (balance, sum))| i128::from(*balance) != *sum)
+                .collect(),
+            pools: self
+                .gambling_tables
+                .iter()
+                .map(|(&id, table)| (id, table.pot))
+                .chain(self.markets.iter().map(|(&id, market)| (id, market.pot())))
+                .map(|(id, pot)| (id, (pot, pools.get(&id).copied().unwrap_or_default())))
+                .filter(|(_, (pot, sum))| i128::from(*pot) != *sum)
+                .collect(),
+        }
+    }
+}
+
+pub(crate) struct Audit {
+    pub(crate) accounts: BTreeMap<UserId, (u64, i128)>,
+    /// Gambling tables and markets
+    pub(crate) pools: BTreeMap<Uuid, (u64, i128)>,
+}
+
+/// Folds old transactions, see [`ConfigT::compact_ledger`]
+pub(crate) async fn compact_ledger(data: &impl With<ConfigT>) -> Result<()> {
+    let now = Utc::now();
+    // most of the time there's nothing to fold, so don't mark the config as changed
+    if data.with_ok(|cfg| cfg.foldable(now)).await? == 0 {
+        return Ok(());
+    }
+    let folded = data.with_mut_ok(|cfg| cfg.compact_ledger(now)).await?;
+    if folded > 0 {
+        tracing::info!("Folded {folded} old transactions into adjustments");
+    }
+    Ok(())
+}
+
+impl Currency {
+    /// Formats an amount with its sign
+    pub(crate) fn fmt_signed(&self, amount: i64) -> String {
+        format!("{}{}", if amount < 0 { "-" } else { "+" }, self.fmt(amount.unsigned_abs()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::GamblingTable;
+
+    #[test]
+    fn compaction_keeps_balances_and_open_tables() {
+        let (a, b) = (UserId::new(1), UserId::new(2));
+        let table = Uuid::new_v4();
+        let mut cfg = ConfigT::default();
+        cfg.transact(a, 100, TransactionKind::Income { days: 1 }).unwrap();
+        cfg.transact(b, 50, TransactionKind::Income { days: 1 }).unwrap();
+        cfg.transact(b, -50, TransactionKind::BuyIn { table, name: "Table".to_string() }).unwrap();
+        cfg.gambling_tables.insert(
+            table,
+            GamblingTable {
+                dealer: a,
+                name: "Table".to_string(),
+                description: None,
+                buyin: 50,
+                players: BTreeMap::from([(b, 50)]),
+                pot: 50,
+                last_activity: Utc::now(),
+                message: None,
+            },
+        );
+
+        let later = Utc::now() + cfg.ledger_retention + TimeDelta::days(1);
+        assert_eq!(cfg.compact_ledger(later), 2);
+        // the buy-in is kept while the table is open
+        assert_eq!(cfg.ledger.len(), 3);
+        let audit = cfg.audit();
+        assert!(audit.accounts.is_empty() && audit.pools.is_empty());
+        assert_eq!(cfg.compact_ledger(later), 0);
+
+        cfg.gambling_tables.clear();
+        assert_eq!(cfg.compact_ledger(later), 1);
+        assert_eq!(
+            cfg.ledger,
+            [Transaction { time: cfg.ledger[0].time, user: a, amount: 100, kind: TransactionKind::Adjustment }]
+        );
+    }
+}
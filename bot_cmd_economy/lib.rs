@@ -1,24 +1,47 @@
 mod account;
+mod activity;
+mod blackjack;
 mod buy_in;
+mod casino;
 mod gamble;
 mod leaderboard;
+mod ledger;
+mod loan;
+mod market;
+mod pay;
 mod pay_out;
+mod policy;
+mod shop;
+mod table;
 
 pub use crate::account::*;
+pub use crate::activity::*;
+pub use crate::blackjack::*;
 pub use crate::buy_in::*;
+pub use crate::casino::*;
 pub use crate::gamble::*;
 pub use crate::leaderboard::*;
+pub use crate::loan::*;
+pub use crate::market::*;
+pub use crate::pay::*;
 pub use crate::pay_out::*;
-use bot_core::With;
+pub use crate::policy::*;
+pub use crate::shop::*;
+pub use crate::table::*;
 use bot_core::lock_set::LockSet;
-use chrono::{DateTime, Utc};
+use bot_core::{State, With};
+use chrono::{DateTime, TimeDelta, Utc};
+use dashmap::DashMap;
 use eyre::Result;
 use poise::CreateReply;
 use poise::serenity_prelude::{
-    ButtonStyle, Colour, CreateActionRow, CreateButton, CreateEmbed, Mentionable as _, UserId,
+    ButtonStyle, ChannelId, Colour, Context, CreateActionRow, CreateButton, CreateEmbed, GuildId, Mentionable as _,
+    MessageId, UserId,
 };
 use serde_with::{DisplayFromStr, serde_as};
 use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thousands::Separable;
 use uuid::Uuid;
 
@@ -27,6 +50,8 @@ pub const TABLE_SELECT_ID: &str = "economy.table_select";
 pub const BUYIN_BUTTON_ID: &str = "economy.buyin";
 pub const PAY_TABLE_BUTTON_ID: &str = "economy.pay_table";
 pub const PAY_PLAYER_BUTTON_ID: &str = "economy.pay_player";
+pub const MARKET_STAKE_SELECT_ID: &str = "economy.market_stake";
+pub const MARKET_RESOLVE_BUTTON_ID: &str = "economy.market_resolve";
 
 #[serde_as]
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, sensible::Default)]
@@ -36,11 +61,80 @@ pub struct ConfigT {
     account: BTreeMap<UserId, UserAccount>,
     #[serde_as(as = "BTreeMap<DisplayFromStr, _>")]
     gambling_tables: BTreeMap<Uuid, GamblingTable>,
+    #[serde(default)]
+    ledger: Vec<ledger::Transaction>,
+    /// Transactions are folded into one adjustment per account after this, unless their table or market is still open
+    #[serde(default = "ledger::default_retention", with = "bot_core::serde::td_seconds")]
+    #[default(ledger::default_retention())]
+    ledger_retention: TimeDelta,
+    #[serde(default)]
+    #[serde_as(as = "BTreeMap<DisplayFromStr, _>")]
+    markets: BTreeMap<Uuid, market::Market>,
+    #[serde(default)]
+    transfer: pay::TransferConfig,
+    #[serde(default)]
+    casino: casino::CasinoConfig,
+    /// The seeds last revealed by /fairness, so that members can still check their games later
+    #[serde(default)]
+    revealed_seeds: BTreeMap<UserId, casino::Seeds>,
+    #[serde(default)]
+    shop: BTreeMap<String, shop::ShopItem>,
+    #[serde(default)]
+    timed_roles: Vec<shop::TimedRole>,
+    #[serde(default)]
+    activity_income: activity::ActivityIncome,
+    #[serde(default)]
+    activity_earnings: BTreeMap<UserId, activity::Earnings>,
+    #[serde(default)]
+    tables: table::TableConfig,
+    #[serde(default)]
+    policy: policy::MonetaryPolicy,
+    #[serde(default)]
+    money_supply_history: Vec<policy::SupplySnapshot>,
+    #[serde(default)]
+    #[serde_as(as = "BTreeMap<DisplayFromStr, _>")]
+    loans: BTreeMap<Uuid, loan::Loan>,
 }
 
 #[derive(Default)]
 pub struct StateT {
     table_locks: LockSet<Uuid>,
+    account_locks: LockSet<UserId>,
+    last_paid_message: DashMap<UserId, Instant>,
+    /// When the members in voice started earning
+    voice_sessions: DashMap<UserId, Instant>,
+    /// Seeds of the players' next games, saved to a file instead of the config so that the server seeds stay secret
+    /// until revealed
+    seeds: DashMap<UserId, casino::Seeds>,
+    seeds_lock: tokio::sync::Mutex<()>,
+}
+
+pub async fn setup(ctx: Context, data: impl With<ConfigT> + State<StateT> + State<GuildId>) -> Result<()> {
+    let state: Arc<StateT> = data.state();
+    state.load_seeds(*data.state()).await?;
+
+    tracing::debug!("Spawning economy worker");
+    tokio::spawn(async move {
+        loop {
+            if let Err(error) = shop::remove_expired_roles(&ctx, &data).await {
+                tracing::error!("Error removing expired shop roles: {error:?}");
+            }
+            if let Err(error) = table::close_expired_tables(&ctx, &data).await {
+                tracing::error!("Error closing expired gambling tables: {error:?}");
+            }
+            if let Err(error) = policy::run_monetary_policy(&data).await {
+                tracing::error!("Error applying the monetary policy: {error:?}");
+            }
+            if let Err(error) = ledger::compact_ledger(&data).await {
+                tracing::error!("Error compacting the ledger: {error:?}");
+            }
+            if let Err(error) = activity::pay_voice_sessions(&ctx, &data).await {
+                tracing::error!("Error paying for voice time: {error:?}");
+            }
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        }
+    });
+    Ok(())
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Default)]
@@ -78,6 +172,12 @@ struct GamblingTable {
     buyin: u64,
     players: BTreeMap<UserId, u64>,
     pot: u64,
+    /// Last buy-in or payout, the table expires some time after it
+    #[serde(default = "Utc::now")]
+    last_activity: DateTime<Utc>,
+    /// Where the table was posted, its buttons are removed when it's closed
+    #[serde(default)]
+    message: Option<(ChannelId, MessageId)>,
 }
 
 impl GamblingTable {
@@ -0,0 +1,317 @@
+use crate::ledger::TransactionKind;
+use crate::{ConfigT, Currency};
+use bot_core::ext::create_reply::CreateReplyExt;
+use bot_core::ext::option::OptionExt as _;
+use bot_core::time::human::parse_datetime;
+use bot_core::time::local_timezone;
+use bot_core::{CmdContext, With};
+use chrono::{DateTime, Utc};
+use eyre::{OptionExt as _, Result, ensure};
+use itertools::Itertools;
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    ButtonStyle, Colour, CreateActionRow, CreateButton, CreateEmbed, Mentionable as _, User, UserId,
+};
+use std::time::Duration;
+use uuid::Uuid;
+
+const OFFER_TIME: Duration = Duration::from_secs(10 * 60);
+
+/// Money lent from one member to another, repaid from the borrower's income once it's due
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Loan {
+    pub(crate) lender: UserId,
+    pub(crate) borrower: UserId,
+    /// What's still owed, including interest
+    pub(crate) owed: u64,
+    pub(crate) due: DateTime<Utc>,
+}
+
+impl Loan {
+    pub(crate) fn describe(&self, cur: &Currency, user: UserId) -> String {
+        let overdue = if self.due <= Utc::now() { " ⚠️" } else { "" };
+        let due = format!("due <t:{}:R>{overdue}", self.due.timestamp());
+        if user == self.borrower {
+            format!("{} owed to {}, {due}", cur.fmt(self.owed), self.lender.mention())
+        } else {
+            format!("{} owed by {}, {due}", cur.fmt(self.owed), self.borrower.mention())
+        }
+    }
+}
+
+impl ConfigT {
+    /// Pays out the loan, the lender pays the transfer fee on top and it counts towards their daily cap
+    fn lend(
+        &mut self,
+        cur: &Currency,
+        lender: UserId,
+        borrower: UserId,
+        amount: u64,
+        fee: u64,
+        loan: Loan,
+    ) -> Result<()> {
+        let balance = self.account.get(&lender).map_or(0, |a| a.balance);
+        let total = amount.checked_add(fee).ok_or_eyre("The amount is too high")?;
+        ensure!(
+            balance >= total,
+            "{} doesn't have enough money anymore: {} < {}",
+            lender.mention(),
+            cur.fmt(balance),
+            cur.fmt(total)
+        );
+        self.check_transfer(cur, lender, amount, fee)?;
+        self.transact(lender, -i64::try_from(amount)?, TransactionKind::Loan { user: borrower })?;
+        if fee != 0 {
+            self.collect(lender, fee, TransactionKind::TransferFee)?;
+        }
+        self.transact(borrower, amount.try_into()?, TransactionKind::Loan { user: lender })?;
+        self.loans.insert(Uuid::new_v4(), loan);
+        Ok(())
+    }
+
+    /// Pays back the member's loans with up to `budget`, the ones due first. Returns how much was repaid.
+    pub(crate) fn repay_loans(&mut self, borrower: UserId, budget: u64, only_due: bool) -> Result<u64> {
+        let now = Utc::now();
+        let loans = self
+            .loans
+            .iter()
+            .filter(|(_, loan)| loan.borrower == borrower && (!only_due || loan.due <= now))
+            .sorted_by_key(|(_, loan)| loan.due)
+            .map(|(&id, loan)| (id, loan.lender, loan.owed))
+            .collect_vec();
+
+        let mut repaid = 0;
+        for (id, lender, owed) in loans {
+            let amount = owed.min(budget - repaid);
+            if amount == 0 {
+                break;
+            }
+            self.transact(borrower, -i64::try_from(amount)?, TransactionKind::LoanRepayment { user: lender })?;
+            self.transact(lender, amount.try_into()?, TransactionKind::LoanRepayment { user: borrower })?;
+            repaid += amount;
+
+            let loan = self.loans.get_mut(&id).some()?;
+            loan.owed -= amount;
+            if loan.owed == 0 {
+                self.loans.remove(&id);
+            }
+        }
+        Ok(repaid)
+    }
+
+    /// Loans the member lent or borrowed, the ones due first
+    pub(crate) fn loans_of(&self, user: UserId) -> Vec<Loan> {
+        self.loans
+            .values()
+            .filter(|loan| loan.lender == user || loan.borrower == user)
+            .sorted_by_key(|loan| loan.due)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Lend money to other members
+#[poise::command(slash_command, guild_only, subcommands("loan_offer", "loan_list", "loan_repay"), subcommand_required)]
+pub async fn loan<D: With<ConfigT>>(_ctx: CmdContext<'_, D>) -> Result<()> {
+    Ok(())
+}
+
+/// Offer a loan, it's paid out once the borrower accepts
+#[poise::command(slash_command, guild_only, rename = "offer")]
+pub async fn loan_offer<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "Who to lend to"] user: User,
+    #[description = "How much to lend"]
+    #[min = 1]
+    amount: u64,
+    #[description = "Interest in percent, added once"] interest: Option<u64>,
+    #[autocomplete = bot_core::autocomplete::datetime]
+    #[description = "When it has to be repaid, e.g. fri 18:00"]
+    due: String,
+) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let lender = ctx.author().id;
+    let borrower = user.id;
+    ensure!(borrower != lender, "You can't lend to yourself");
+    ensure!(!user.bot, "You can't lend to bots");
+
+    let due = parse_datetime(&due, &local_timezone(), Utc::now()).ok_or_eyre("Invalid due date")?.to_utc();
+    ensure!(due > Utc::now(), "The loan has to be due in the future");
+
+    let fee = ctx.data().with(|cfg| cfg.transfer.fee(amount)).await?;
+    ctx.data().with(|cfg| cfg.check_transfer(&cur, lender, amount, fee)).await?;
+
+    let interest = amount.checked_mul(interest.unwrap_or(0)).ok_or_eyre("The interest is too high")?.div_ceil(100);
+    let owed = amount.checked_add(interest).ok_or_eyre("The interest is too high")?;
+    let fee_line = if fee != 0 { format!("\nFee for {}: {}", lender.mention(), cur.fmt(fee)) } else { String::new() };
+    let description = format!(
+        "{} offers {} a loan of **{}**\nTo repay: {}, due <t:{}:R>{fee_line}\n\nUnpaid debts are taken from income once they're due.",
+        lender.mention(),
+        borrower.mention(),
+        cur.fmt(amount),
+        cur.fmt(owed),
+        due.timestamp()
+    );
+    let embed = CreateEmbed::new().title("🤝 Loan").description(description);
+
+    let accept_id = "~economy.loan_accept";
+    let decline_id = "~economy.loan_decline";
+    let offer = |colour: Colour, active: bool| {
+        let buttons = vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(accept_id).label("Accept").style(ButtonStyle::Success),
+            CreateButton::new(decline_id).label("Decline").style(ButtonStyle::Danger),
+        ])];
+        CreateReply::new()
+            .content(borrower.mention().to_string())
+            .embed(embed.clone().colour(colour))
+            .components(if active { buttons } else { vec![] })
+    };
+
+    let handle = ctx.send(offer(Colour::GOLD, true)).await?;
+    let message = handle.message().await?;
+
+    let mut accepted = false;
+    while let Some(interaction) = message.await_component_interaction(ctx.serenity_context()).timeout(OFFER_TIME).await
+    {
+        let user = interaction.user.id;
+        if interaction.data.custom_id == decline_id && (user == borrower || user == lender) {
+            interaction.defer(ctx.serenity_context()).await?;
+            break;
+        }
+        if interaction.data.custom_id != accept_id || user != borrower {
+            let reply = CreateReply::new().content("This offer isn't for you").ephemeral(true);
+            reply.respond_to_component(ctx.serenity_context(), &interaction).await?;
+            continue;
+        }
+
+        let loan = Loan { lender, borrower, owed, due };
+        match ctx.data().with_mut(|cfg| cfg.lend(&cur, lender, borrower, amount, fee, loan)).await {
+            Ok(()) => {
+                interaction.defer(ctx.serenity_context()).await?;
+                accepted = true;
+                break;
+            }
+            Err(e) => {
+                let reply = CreateReply::new().content(e.to_string()).ephemeral(true);
+                reply.respond_to_component(ctx.serenity_context(), &interaction).await?;
+            }
+        }
+    }
+
+    if accepted {
+        tracing::info!("User {lender} lent {} to {borrower}", cur.fmt(amount));
+    }
+    let colour = if accepted { Colour::DARK_GREEN } else { Colour::DARKER_GREY };
+    handle.edit(ctx, offer(colour, false)).await?;
+
+    Ok(())
+}
+
+/// See the loans you lent or borrowed
+#[poise::command(slash_command, guild_only, rename = "list")]
+pub async fn loan_list<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "Member, defaults to you"] user: Option<UserId>,
+) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let user = user.unwrap_or(ctx.author().id);
+    let loans = ctx.data().with_ok(|cfg| cfg.loans_of(user)).await?;
+    ensure!(!loans.is_empty(), "{} has no outstanding loans", user.mention());
+
+    let (debts, credits): (Vec<_>, Vec<_>) = loans.iter().partition(|loan| loan.borrower == user);
+    let mut embed = CreateEmbed::new().title("🤝 Loans").description(user.mention().to_string()).colour(Colour::GOLD);
+    if !debts.is_empty() {
+        embed = embed.field("Borrowed", debts.iter().map(|loan| loan.describe(&cur, user)).join("\n"), false);
+    }
+    if !credits.is_empty() {
+        embed = embed.field("Lent", credits.iter().map(|loan| loan.describe(&cur, user)).join("\n"), false);
+    }
+    ctx.send(CreateReply::new().embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Pay back your loans early, the ones due first
+#[poise::command(slash_command, guild_only, rename = "repay")]
+pub async fn loan_repay<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "How much to pay back, defaults to as much as you can"]
+    #[min = 1]
+    amount: Option<u64>,
+) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let user = ctx.author().id;
+    let (repaid, owed) = ctx
+        .data()
+        .with_mut(|cfg| {
+            ensure!(cfg.loans.values().any(|loan| loan.borrower == user), "You don't owe anyone money");
+            let balance = cfg.account.get(&user).map_or(0, |a| a.balance);
+            let repaid = cfg.repay_loans(user, amount.unwrap_or(u64::MAX).min(balance), false)?;
+            let owed = cfg.loans.values().filter(|loan| loan.borrower == user).map(|loan| loan.owed).sum::<u64>();
+            Ok((repaid, owed))
+        })
+        .await?;
+    ensure!(repaid > 0, "You don't have any money to pay back");
+    tracing::info!("User {user} repaid {} of loans", cur.fmt(repaid));
+
+    let mut description = format!("Repaid {}", cur.fmt(repaid));
+    if owed > 0 {
+        description += &format!("\nStill owed: {}", cur.fmt(owed));
+    }
+    let embed = CreateEmbed::new().title("🤝 Loans").description(description).colour(Colour::DARK_GREEN);
+    ctx.send(CreateReply::new().embed(embed)).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn repay_due_loans_first() {
+        let (lender, other, borrower) = (UserId::new(1), UserId::new(2), UserId::new(3));
+        let cur = Currency::default();
+        let mut cfg = ConfigT::default();
+        cfg.account.entry(lender).or_default().balance = 100;
+        cfg.account.entry(other).or_default().balance = 100;
+
+        let now = Utc::now();
+        let later = Loan { lender, borrower, owed: 60, due: now + TimeDelta::days(1) };
+        let overdue = Loan { lender: other, borrower, owed: 60, due: now - TimeDelta::days(1) };
+        cfg.lend(&cur, lender, borrower, 50, 0, later).unwrap();
+        cfg.lend(&cur, other, borrower, 50, 0, overdue).unwrap();
+        assert!(cfg.lend(&cur, lender, borrower, 80, 0, Loan { lender, borrower, owed: 80, due: now }).is_err());
+
+        // only the overdue loan is taken from income
+        assert_eq!(cfg.repay_loans(borrower, 100, true).unwrap(), 60);
+        assert_eq!(cfg.account[&other].balance, 110);
+        assert_eq!(cfg.loans_of(borrower).len(), 1);
+
+        assert_eq!(cfg.repay_loans(borrower, 40, false).unwrap(), 40);
+        assert_eq!(cfg.loans_of(lender)[0].owed, 20);
+        assert_eq!(cfg.account[&borrower].balance, 0);
+    }
+
+    #[test]
+    fn loans_pay_the_transfer_fee_and_count_towards_the_cap() {
+        let (lender, borrower) = (UserId::new(1), UserId::new(2));
+        let cur = Currency::default();
+        let mut cfg = ConfigT::default();
+        cfg.transfer.fee_percent = 10;
+        cfg.transfer.daily_cap = Some(100);
+        cfg.account.entry(lender).or_default().balance = 1000;
+
+        let loan = || Loan { lender, borrower, owed: 60, due: Utc::now() + TimeDelta::days(1) };
+        let fee = cfg.transfer.fee(60).unwrap();
+        cfg.lend(&cur, lender, borrower, 60, fee, loan()).unwrap();
+        assert_eq!(cfg.account[&lender].balance, 934);
+        assert_eq!(cfg.account[&borrower].balance, 60);
+
+        // 60 were lent today already
+        assert!(cfg.lend(&cur, lender, borrower, 60, fee, loan()).is_err());
+        assert!(cfg.check_transfer(&cur, lender, 50, 5).is_err());
+        cfg.check_transfer(&cur, lender, 40, 4).unwrap();
+    }
+}
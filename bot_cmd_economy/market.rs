@@ -0,0 +1,345 @@
+use crate::ledger::TransactionKind;
+use crate::{ConfigT, Currency, MARKET_RESOLVE_BUTTON_ID, MARKET_STAKE_SELECT_ID};
+use bot_core::ext::create_reply::CreateReplyExt;
+use bot_core::ext::option::OptionExt as _;
+use bot_core::time::human::parse_datetime;
+use bot_core::time::local_timezone;
+use bot_core::{CmdContext, EvtContext, With};
+use chrono::{DateTime, Utc};
+use eyre::{OptionExt as _, Result, ensure, eyre};
+use itertools::Itertools;
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    ButtonStyle, Colour, ComponentInteraction, ComponentInteractionDataKind, CreateActionRow, CreateButton,
+    CreateEmbed, CreateInputText, CreateQuickModal, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
+    InputTextStyle, Mentionable as _, UserId,
+};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// A parimutuel pool: the stakes on the outcome that happens share the whole pot
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Market {
+    creator: UserId,
+    question: String,
+    outcomes: Vec<String>,
+    closes: DateTime<Utc>,
+    /// Stake of each member on each outcome, by index
+    stakes: BTreeMap<UserId, BTreeMap<usize, u64>>,
+}
+
+impl Market {
+    pub(crate) fn pot(&self) -> u64 {
+        self.stakes.values().flat_map(|s| s.values()).sum()
+    }
+
+    fn total(&self, outcome: usize) -> u64 {
+        self.stakes.values().filter_map(|s| s.get(&outcome)).sum()
+    }
+
+    /// Splits the pot in proportion to the stakes on the winning outcome, refunds everyone if nobody picked it
+    fn settle(&self, winner: usize) -> Vec<(UserId, u64)> {
+        let (pot, winning) = (self.pot(), self.total(winner));
+        if winning == 0 {
+            return self.refunds();
+        }
+
+        let mut payouts = self
+            .stakes
+            .iter()
+            .filter_map(|(&user, s)| s.get(&winner).map(|&stake| (user, stake)))
+            .map(|(user, stake)| (user, (u128::from(pot) * u128::from(stake) / u128::from(winning)) as u64))
+            .collect_vec();
+
+        // the rounding leftovers go to the biggest winner
+        let rest = pot - payouts.iter().map(|x| x.1).sum::<u64>();
+        if let Some(biggest) = payouts.iter_mut().max_by_key(|(_, payout)| *payout) {
+            biggest.1 += rest;
+        }
+        payouts
+    }
+
+    fn refunds(&self) -> Vec<(UserId, u64)> {
+        self.stakes.iter().map(|(&user, s)| (user, s.values().sum())).collect()
+    }
+
+    fn embed(&self, cur: &Currency, winner: Option<usize>) -> CreateEmbed {
+        let pot = self.pot();
+        let outcomes = self
+            .outcomes
+            .iter()
+            .enumerate()
+            .map(|(i, outcome)| {
+                let total = self.total(i);
+                let share = if pot == 0 { 0 } else { total * 100 / pot };
+                let mark = if winner == Some(i) { "✅ " } else { "" };
+                format!("{mark}**{outcome}** — {} ({share}%)", cur.fmt(total))
+            })
+            .join("\n");
+        let closes = if self.closes > Utc::now() { "Closes" } else { "Closed" };
+
+        CreateEmbed::new()
+            .title(format!("📊 {}", self.question))
+            .description(outcomes)
+            .field("Pot", cur.fmt(pot), true)
+            .field(closes, format!("<t:{}:R>", self.closes.timestamp()), true)
+            .field("Created by", self.creator.mention().to_string(), true)
+            .colour(Colour::GOLD)
+    }
+
+    fn reply(&self, cur: &Currency, id: Uuid) -> CreateReply {
+        let options = self
+            .outcomes
+            .iter()
+            .enumerate()
+            .map(|(i, outcome)| CreateSelectMenuOption::new(outcome, i.to_string()))
+            .collect_vec();
+        let stake_select =
+            CreateSelectMenu::new(format!("{MARKET_STAKE_SELECT_ID}:{id}"), CreateSelectMenuKind::String { options })
+                .placeholder("Stake on an outcome...");
+        let resolve_button = CreateButton::new(format!("{MARKET_RESOLVE_BUTTON_ID}:{id}"))
+            .style(ButtonStyle::Secondary)
+            .label("Resolve")
+            .emoji('🏁');
+
+        CreateReply::new()
+            .embed(self.embed(cur, None))
+            .components(vec![CreateActionRow::SelectMenu(stake_select), CreateActionRow::Buttons(vec![resolve_button])])
+    }
+
+    fn resolved_reply(&self, cur: &Currency, winner: Option<usize>, payouts: &[(UserId, u64)]) -> CreateReply {
+        let title = if winner.is_some() { "Payouts" } else { "Cancelled, all stakes were refunded" };
+        let lines = payouts
+            .iter()
+            .sorted_by(|(_, p1), (_, p2)| p2.cmp(p1))
+            .map(|(user, payout)| format!("{} {}", user.mention(), cur.fmt(*payout)))
+            .collect_vec();
+        let embed = self.embed(cur, winner).field(title, payouts_value(lines), false).colour(Colour::DARKER_GREY);
+        CreateReply::new().embed(embed).components(vec![])
+    }
+}
+
+/// Join payout lines into an embed field value, cutting it off at Discord's limit
+fn payouts_value(lines: Vec<String>) -> String {
+    if lines.is_empty() {
+        return "Nobody staked".to_string();
+    }
+    let mut value = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let rest = format!("… and {} more", lines.len() - i);
+        if value.len() + line.len() + rest.len() + 1 > 1024 {
+            value.push_str(&rest);
+            break;
+        }
+        value.push_str(line);
+        value.push('\n');
+    }
+    value
+}
+
+impl ConfigT {
+    fn stake(&mut self, cur: &Currency, id: Uuid, user: UserId, outcome: usize, amount: u64) -> Result<Market> {
+        let market = self.markets.get(&id).ok_or_eyre("Market doesn't exist")?;
+        ensure!(market.closes > Utc::now(), "Betting has closed");
+        ensure!(outcome < market.outcomes.len(), "Unknown outcome");
+        ensure!(amount > 0, "Stake at least {}", cur.fmt(1));
+        let balance = self.account.get(&user).map_or(0, |a| a.balance);
+        ensure!(balance >= amount, "You don't have enough money: {} < {}", cur.fmt(balance), cur.fmt(amount));
+
+        let kind = TransactionKind::Stake { market: id, question: market.question.clone() };
+        self.transact(user, -i64::try_from(amount)?, kind)?;
+        let market = self.markets.get_mut(&id).some()?;
+        *market.stakes.entry(user).or_default().entry(outcome).or_default() += amount;
+        tracing::info!("User {user} staked {} on {} in market {id}", cur.fmt(amount), market.outcomes[outcome]);
+
+        Ok(market.clone())
+    }
+
+    /// Pays out the winners, or refunds everyone if there's no winner
+    fn resolve_market(&mut self, id: Uuid, winner: Option<usize>) -> Result<(Market, Vec<(UserId, u64)>)> {
+        let market = self.markets.remove(&id).ok_or_eyre("Market doesn't exist")?;
+        let payouts = match winner {
+            Some(winner) => market.settle(winner),
+            None => market.refunds(),
+        };
+
+        for &(user, amount) in payouts.iter().filter(|(_, amount)| *amount != 0) {
+            let question = market.question.clone();
+            let kind = match winner {
+                Some(_) => TransactionKind::MarketPayout { market: id, question },
+                None => TransactionKind::MarketRefund { market: id, question },
+            };
+            self.transact(user, amount.try_into()?, kind)?;
+        }
+
+        Ok((market, payouts))
+    }
+}
+
+/// Create a betting market, the pot is split among everyone who picked the right outcome
+#[poise::command(slash_command, guild_only)]
+pub async fn market<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "What's being bet on"] question: String,
+    #[description = "The possible outcomes, separated by commas"] outcomes: String,
+    #[autocomplete = bot_core::autocomplete::datetime]
+    #[description = "When betting closes, e.g. 20:00 or fri 18:00"]
+    closes: String,
+) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let now = Utc::now();
+    let closes = parse_datetime(&closes, &local_timezone(), now).ok_or_eyre("Invalid closing time")?.to_utc();
+    ensure!(closes > now, "Betting has to close in the future");
+
+    // the question is the embed title after an emoji, the outcomes are select menu labels
+    ensure!(question.chars().count() <= 250, "The question can be at most 250 characters long");
+    let outcomes = outcomes.split(',').map(|o| o.trim().to_string()).filter(|o| !o.is_empty()).unique().collect_vec();
+    ensure!((2..=25).contains(&outcomes.len()), "A market needs between 2 and 25 outcomes");
+    ensure!(outcomes.iter().all(|o| o.chars().count() <= 100), "Outcomes can be at most 100 characters long");
+
+    let market = Market { creator: ctx.author().id, question, outcomes, closes, stakes: Default::default() };
+    let id = Uuid::new_v4();
+    ctx.data().with_mut_ok(|cfg| cfg.markets.insert(id, market.clone())).await?;
+
+    ctx.send(market.reply(&cur, id)).await?;
+
+    Ok(())
+}
+
+pub async fn btn_market_stake(
+    ctx: EvtContext<'_, impl With<ConfigT>>,
+    component: &ComponentInteraction,
+    param: &str,
+) -> Result<()> {
+    let ComponentInteractionDataKind::StringSelect { values } = &component.data.kind else {
+        return Ok(());
+    };
+    let id = Uuid::try_parse(param)?;
+    let outcome: usize = values.first().some()?.parse()?;
+    let cur = Currency::read(ctx.user_data).await?;
+
+    let name = ctx
+        .user_data
+        .with(|cfg| {
+            let market = cfg.markets.get(&id).ok_or_eyre("Market doesn't exist")?;
+            ensure!(market.closes > Utc::now(), "Betting has closed");
+            Ok(market.outcomes.get(outcome).some()?.clone())
+        })
+        .await?;
+
+    let label = format!("Stake on {name}").chars().take(45).collect::<String>();
+    let Some(response) = CreateQuickModal::new("Stake")
+        .field(CreateInputText::new(InputTextStyle::Short, label, "").placeholder(cur.fmt(100)))
+        .timeout(Duration::from_secs(10 * 60))
+        .execute(ctx.serenity_context, component.id, &component.token)
+        .await?
+    else {
+        return Ok(());
+    };
+
+    let input = response.inputs.first().some()?;
+    let amount = input.trim().parse::<u64>().map_err(|_| eyre!("Invalid amount: {input}"))?;
+    response.interaction.defer(ctx.serenity_context).await?;
+
+    let market = ctx.user_data.with_mut(|cfg| cfg.stake(&cur, id, component.user.id, outcome, amount)).await?;
+
+    market.reply(&cur, id).edit_message(ctx.serenity_context, &component.message).await?;
+
+    Ok(())
+}
+
+pub async fn btn_market_resolve(
+    ctx: EvtContext<'_, impl With<ConfigT>>,
+    component: &ComponentInteraction,
+    param: &str,
+) -> Result<()> {
+    let id = Uuid::try_parse(param)?;
+    let cur = Currency::read(ctx.user_data).await?;
+
+    let market = ctx.user_data.with(|cfg| cfg.markets.get(&id).cloned().ok_or_eyre("Market doesn't exist")).await?;
+    let is_admin = component.member.as_ref().and_then(|m| m.permissions).is_some_and(|p| p.manage_guild());
+    ensure!(
+        market.creator == component.user.id || is_admin,
+        "Only {} can resolve this market",
+        market.creator.mention()
+    );
+
+    // until betting closes, the creator can only cancel the market
+    let can_settle = market.closes <= Utc::now() || is_admin;
+    let options = market
+        .outcomes
+        .iter()
+        .enumerate()
+        .filter(|_| can_settle)
+        .map(|(i, outcome)| CreateSelectMenuOption::new(outcome, i.to_string()))
+        .chain([CreateSelectMenuOption::new("Cancel the market", "cancel").description("Refunds all stakes")])
+        .collect_vec();
+    let select = CreateSelectMenu::new("~economy.market_outcome", CreateSelectMenuKind::String { options })
+        .placeholder("What happened?");
+    CreateReply::new()
+        .components(vec![CreateActionRow::SelectMenu(select)])
+        .ephemeral(true)
+        .respond_to_component(ctx.serenity_context, component)
+        .await?;
+
+    let response = component.get_response(ctx.serenity_context).await?;
+    let Some(interaction) =
+        response.await_component_interaction(ctx.serenity_context).timeout(Duration::from_secs(5 * 60)).await
+    else {
+        return Ok(());
+    };
+    let ComponentInteractionDataKind::StringSelect { values } = &interaction.data.kind else {
+        return Ok(());
+    };
+    // anything that isn't an outcome cancels the market
+    let winner = values.first().some()?.parse::<usize>().ok();
+    ensure!(winner.is_none() || can_settle, "Betting is still open, the market can only be cancelled");
+
+    let (market, payouts) = ctx.user_data.with_mut(|cfg| cfg.resolve_market(id, winner)).await?;
+    tracing::info!("Resolved market {id} with outcome {winner:?}");
+
+    CreateReply::new()
+        .content("Resolved the market")
+        .components(vec![])
+        .update_to_component(ctx.serenity_context, &interaction)
+        .await?;
+    market.resolved_reply(&cur, winner, &payouts).edit_message(ctx.serenity_context, &component.message).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn settle_splits_pot_proportionally() {
+        let (a, b, c) = (UserId::new(1), UserId::new(2), UserId::new(3));
+        let market = Market {
+            creator: a,
+            question: "Who wins?".to_string(),
+            outcomes: vec!["Red".to_string(), "Blue".to_string(), "Nobody".to_string()],
+            closes: Utc::now(),
+            stakes: BTreeMap::from([
+                (a, BTreeMap::from([(0, 100)])),
+                (b, BTreeMap::from([(0, 200)])),
+                (c, BTreeMap::from([(1, 701)])),
+            ]),
+        };
+
+        assert_eq!(market.settle(0), vec![(a, 333), (b, 668)]);
+        assert_eq!(market.settle(1), vec![(c, 1001)]);
+        // nobody picked the outcome, so everyone gets their stake back
+        assert_eq!(market.settle(2), vec![(a, 100), (b, 200), (c, 701)]);
+    }
+
+    #[test]
+    fn payouts_value_fits_a_field() {
+        assert_eq!(payouts_value(vec![]), "Nobody staked");
+        let lines = (0..200).map(|i| format!("<@{}> 1000 coins", 100_000_000_000_000_000u64 + i)).collect_vec();
+        let value = payouts_value(lines);
+        assert!(value.len() <= 1024);
+        assert!(value.ends_with("more"));
+    }
+}
@@ -0,0 +1,125 @@
+use crate::ledger::TransactionKind;
+use crate::{ConfigT, Currency, StateT};
+use bot_core::{CmdContext, State, With};
+use chrono::{TimeDelta, Utc};
+use eyre::{OptionExt as _, Result, ensure};
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    ButtonStyle, Colour, CreateActionRow, CreateButton, CreateEmbed, Mentionable as _, User, UserId,
+};
+use std::time::Duration;
+
+/// Give money to another member
+#[poise::command(slash_command, guild_only)]
+pub async fn pay<D: With<ConfigT> + State<StateT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "Who to pay"] user: User,
+    #[description = "How much to pay"]
+    #[min = 1]
+    amount: u64,
+    #[description = "What the money is for"] note: Option<String>,
+) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let sender = ctx.author().id;
+    ensure!(user.id != sender, "You can't pay yourself");
+    ensure!(!user.bot, "You can't pay bots");
+
+    // keep the balance stable until the transfer is confirmed
+    let _lock = ctx.data().state().account_locks.get(sender);
+    let _lock = _lock.lock().await;
+
+    let fee = ctx.data().with(|cfg| cfg.transfer.fee(amount)).await?;
+    ctx.data().with(|cfg| cfg.check_transfer(&cur, sender, amount, fee)).await?;
+
+    let mut description = format!("{} → {}: **{}**", sender.mention(), user.mention(), cur.fmt(amount));
+    if fee != 0 {
+        description += &format!("\nFee: {}", cur.fmt(fee));
+    }
+    if let Some(note) = &note {
+        description += &format!("\n> {note}");
+    }
+    let embed = CreateEmbed::new().title("Pay").description(description).colour(Colour::GOLD);
+
+    let confirm_id = "~economy.confirm";
+    let cancel_id = "~economy.cancel";
+
+    let handle = ctx
+        .send(CreateReply::new().embed(embed.clone()).components(vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(confirm_id).label("Confirm").style(ButtonStyle::Success),
+            CreateButton::new(cancel_id).label("Cancel").style(ButtonStyle::Danger),
+        ])]))
+        .await?;
+    let message = handle.message().await?;
+
+    let interaction = message
+        .await_component_interaction(ctx.serenity_context())
+        .author_id(sender)
+        .timeout(Duration::from_secs(60))
+        .await;
+
+    // deactivate confirmation message in all cases
+    handle.edit(ctx, CreateReply::new().embed(embed.clone().colour(Colour::DARKER_GREY)).components(vec![])).await?;
+
+    let Some(interaction) = interaction.filter(|i| i.data.custom_id == confirm_id) else { return Ok(()) };
+    interaction.defer(ctx.serenity_context()).await?;
+
+    ctx.data()
+        .with_mut(|cfg| {
+            cfg.check_transfer(&cur, sender, amount, fee)?;
+            let kind = TransactionKind::Transfer { user: user.id, note: note.clone() };
+            cfg.transact(sender, -i64::try_from(amount)?, kind)?;
+            if fee != 0 {
+                cfg.collect(sender, fee, TransactionKind::TransferFee)?;
+            }
+            cfg.transact(user.id, amount.try_into()?, TransactionKind::Transfer { user: sender, note: note.clone() })?;
+            Ok(())
+        })
+        .await?;
+    tracing::info!("User {sender} paid {} to {}", cur.fmt(amount), user.id);
+
+    handle.edit(ctx, CreateReply::new().embed(embed.colour(Colour::DARK_GREEN)).components(vec![])).await?;
+
+    Ok(())
+}
+
+/// Limits on transfers between members
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Default)]
+pub(crate) struct TransferConfig {
+    /// Percentage of each transfer or loan that the sender pays on top, the fee goes to the bank
+    pub(crate) fee_percent: u64,
+    /// Most a member can send or lend within 24 hours
+    pub(crate) daily_cap: Option<u64>,
+}
+
+impl TransferConfig {
+    pub(crate) fn fee(&self, amount: u64) -> Result<u64> {
+        Ok(amount.checked_mul(self.fee_percent).ok_or_eyre("The amount is too high")?.div_ceil(100))
+    }
+}
+
+impl ConfigT {
+    pub(crate) fn check_transfer(&self, cur: &Currency, sender: UserId, amount: u64, fee: u64) -> Result<()> {
+        let balance = self.account.get(&sender).map_or(0, |a| a.balance);
+        let total = amount.checked_add(fee).ok_or_eyre("The amount is too high")?;
+        ensure!(balance >= total, "You don't have enough money: {} < {}", cur.fmt(balance), cur.fmt(total));
+
+        if let Some(cap) = self.transfer.daily_cap {
+            let since = Utc::now() - TimeDelta::days(1);
+            let sent = self
+                .ledger
+                .iter()
+                .filter(|t| t.user == sender && t.time > since && t.amount < 0)
+                .filter(|t| matches!(t.kind, TransactionKind::Transfer { .. } | TransactionKind::Loan { .. }))
+                .map(|t| t.amount.unsigned_abs())
+                .sum::<u64>();
+            ensure!(
+                sent.saturating_add(amount) <= cap,
+                "You can only send {} per day, {} are left",
+                cur.fmt(cap),
+                cur.fmt(cap.saturating_sub(sent))
+            );
+        }
+
+        Ok(())
+    }
+}
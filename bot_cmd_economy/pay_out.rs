@@ -1,15 +1,18 @@
+use crate::ledger::TransactionKind;
 use crate::{ConfigT, Currency, GamblingTable, StateT};
 use bot_core::ext::create_reply::CreateReplyExt;
 use bot_core::ext::option::OptionExt as _;
 use bot_core::{EvtContext, State, With, deferred_message, to_snd};
+use chrono::Utc;
 use eyre::{OptionExt, Result, ensure};
 use itertools::Itertools;
 use poise::CreateReply;
 use poise::serenity_prelude::{
-    ButtonStyle, Cache, Colour, ComponentInteraction, CreateActionRow, CreateButton, CreateEmbed, CreateInputText,
-    CreateQuickModal, InputTextStyle, Mentionable as _, Message, ModalInteraction, QuickModalResponse, UserId,
+    ButtonStyle, Colour, ComponentInteraction, CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter,
+    CreateInputText, CreateQuickModal, InputTextStyle, Mentionable as _, Message, ModalInteraction, QuickModalResponse,
+    UserId,
 };
-use std::collections::{BTreeMap, HashMap};
+use std::collections::BTreeMap;
 use std::time::Duration;
 use uuid::Uuid;
 
@@ -25,21 +28,7 @@ pub async fn btn_pay_player(
     let (table, modal) = payout_modal(&ctx, &cur, component, table_id, prefix).await?;
     let Some(modal) = modal else { return Ok(()) };
 
-    let payouts = {
-        let input_map = parse_payout(
-            &ctx.serenity_context.cache,
-            table.players.keys().copied(),
-            modal.inputs.get(0).ok_or_eyre("No input")?,
-        )?;
-
-        table
-            .players
-            .keys()
-            .copied()
-            .map(to_snd(|user_id| input_map.get(user_id).copied()))
-            .filter_map(|(k, v)| v.map(|v| (k, v)))
-            .collect_vec()
-    };
+    let payouts = parse_payout(&table, modal.inputs.first().ok_or_eyre("No input")?)?.into_iter().collect_vec();
 
     payout_confirm(ctx, &cur, table_id, &table, &component.message, &modal.interaction, &payouts).await?;
 
@@ -59,11 +48,7 @@ pub async fn btn_pay_table(
     let Some(modal) = modal else { return Ok(()) };
 
     let payouts = {
-        let input_map = parse_payout(
-            &ctx.serenity_context.cache,
-            table.players.keys().copied(),
-            modal.inputs.get(0).ok_or_eyre("No input")?,
-        )?;
+        let input_map = parse_payout(&table, modal.inputs.first().ok_or_eyre("No input")?)?;
 
         let factor = {
             let sum = input_map.iter().map(|x| x.1).sum::<u64>();
@@ -106,19 +91,17 @@ async fn payout_modal(
     ensure!(table.dealer == component.user.id, "You are not the dealer of this table.");
     ensure!(!table.players.is_empty(), "No players to pay out.");
 
-    let mut name_to_id = BTreeMap::new();
-    for &user_id in table.players.keys() {
-        let name = ctx.serenity_context.cache.user(user_id).some()?.name.clone();
-        name_to_id.insert(name, user_id);
-    }
-
     let template = {
         let mut players_string = String::new();
         for (user_id, bet) in &table.players {
-            let name = &ctx.serenity_context.cache.user(user_id).some()?.name;
-            players_string.push_str(&format!("\n{name}: {bet}"));
+            // the line is matched by the ID, the name is only there for the dealer
+            let name = ctx.serenity_context.cache.user(user_id).map(|u| u.name.clone()).unwrap_or_default();
+            players_string.push_str(&format!("\n{user_id} {name}: {bet}"));
         }
-        format!("{prefix}\n# Pot is {}\n{players_string}", cur.fmt(table.pot))
+        format!(
+            "{prefix}\n# Amounts can also be a percentage of the pot like 50% or rest.\n# Pot is {}\n{players_string}",
+            cur.fmt(table.pot)
+        )
     };
 
     let modal = CreateQuickModal::new("Pay Out")
@@ -150,7 +133,11 @@ async fn payout_confirm(
         .sorted_by(|(_, p1), (_, p2)| p2.cmp(p1))
         .map(|&(id, p)| format!("{} {}", id.mention(), cur.fmt(p)))
         .join("\n");
-    let embed = CreateEmbed::new().title("Pay Out").description(summary).colour(Colour::GOLD);
+    let mut embed = CreateEmbed::new().title("Pay Out").description(summary).colour(Colour::GOLD);
+    let commission_percent = ctx.user_data.with_ok(|cfg| cfg.tables.dealer_commission_percent).await?;
+    if commission_percent > 0.0 {
+        embed = embed.footer(CreateEmbedFooter::new(format!("The dealer keeps {commission_percent}% of every payout")));
+    }
 
     let confirm_id = "~economy.confirm";
     let cancel_id = "~economy.cancel";
@@ -202,16 +189,14 @@ async fn payout_confirm(
     Ok(())
 }
 
-fn parse_payout(cache: &Cache, players: impl IntoIterator<Item = UserId>, input: &str) -> Result<HashMap<UserId, u64>> {
-    let mut name_to_id = HashMap::new();
-    for user_id in players {
-        let name = cache.user(user_id).some()?.name.clone();
-        name_to_id.insert(name, user_id);
-    }
-
-    let mut map = HashMap::new();
+/// Reads lines like `<user id> <name>: <amount>`, where the amount is a number, a percentage of the pot or `rest`.
+/// Fails with every line that couldn't be read.
+fn parse_payout(table: &GamblingTable, input: &str) -> Result<BTreeMap<UserId, u64>> {
+    let mut payouts = BTreeMap::<UserId, u64>::new();
+    let mut rest = None;
+    let mut errors = vec![];
 
-    for line in input.lines() {
+    for (i, line) in input.lines().enumerate() {
         let line = line.trim();
 
         // skip empty lines and comments
@@ -219,18 +204,56 @@ fn parse_payout(cache: &Cache, players: impl IntoIterator<Item = UserId>, input:
             continue;
         }
 
-        let (user, amount) = line.split_once(':').ok_or_eyre("Invalid payout format")?;
-        let user = name_to_id.get(user.trim()).copied().ok_or_eyre("Invalid user in payout")?;
-        if let Ok(payout) = amount.trim().parse::<u64>() {
-            *map.entry(user).or_default() += payout;
+        let n = i + 1;
+        let Some((player, amount)) = line.rsplit_once(':') else {
+            errors.push(format!("Line {n}: `{line}` should look like `<id> <name>: <amount>`"));
+            continue;
+        };
+        let user = player
+            .split_whitespace()
+            .next()
+            .and_then(|id| id.trim_start_matches("<@").trim_end_matches('>').parse::<UserId>().ok())
+            .filter(|user| table.players.contains_key(user));
+        let Some(user) = user else {
+            errors.push(format!("Line {n}: `{}` isn't a player at this table", player.trim()));
+            continue;
+        };
+
+        let amount = amount.trim();
+        if amount.eq_ignore_ascii_case("rest") {
+            if rest.replace(user).is_some() {
+                errors.push(format!("Line {n}: only one player can get the rest"));
+            }
+            continue;
+        }
+        let payout = match amount.strip_suffix('%') {
+            Some(percent) => percent
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|p| (0.0..=100.0).contains(p))
+                .map(|p| (table.pot as f64 * p / 100.0).floor() as u64),
+            None => amount.parse::<u64>().ok(),
+        };
+        match payout {
+            Some(payout) => *payouts.entry(user).or_default() += payout,
+            None => errors.push(format!("Line {n}: `{amount}` isn't an amount, a percentage or rest")),
         }
     }
+    ensure!(errors.is_empty(), "{}", errors.join("\n"));
 
-    ensure!(!map.is_empty(), "No payouts specified");
-    Ok(map)
+    if let Some(user) = rest {
+        let sum = payouts.values().sum::<u64>();
+        ensure!(sum <= table.pot, "The payouts add up to more than the pot: {sum} > {}", table.pot);
+        *payouts.entry(user).or_default() += table.pot - sum;
+    }
+
+    ensure!(!payouts.is_empty(), "No payouts specified");
+    Ok(payouts)
 }
 
-fn apply_payout(cfg: &mut ConfigT, table_id: Uuid, payouts: &[(UserId, u64)]) -> Result<GamblingTable> {
+/// Pays the players from the pot, minus the dealer's commission, and removes the table once the pot is empty
+pub(crate) fn apply_payout(cfg: &mut ConfigT, table_id: Uuid, payouts: &[(UserId, u64)]) -> Result<GamblingTable> {
     let table = cfg.gambling_tables.get_mut(&table_id).ok_or_eyre("Table doesn't exist")?;
 
     let payout_sum = payouts.iter().map(|x| x.1).sum::<u64>();
@@ -238,13 +261,71 @@ fn apply_payout(cfg: &mut ConfigT, table_id: Uuid, payouts: &[(UserId, u64)]) ->
     ensure!(payout_sum <= table.pot, "Pay out sum exceeds the pot. ({payout_sum} > {})", table.pot);
 
     table.pot -= payout_sum;
+    table.last_activity = Utc::now();
+    for &(player_id, _) in payouts {
+        table.players.remove(&player_id);
+    }
+    let table = table.clone();
 
+    let mut commission_sum = 0;
     for &(player_id, payout) in payouts {
-        let account = cfg.account.entry(player_id).or_default();
-        account.balance += payout;
-        table.players.remove(&player_id);
-        tracing::info!("User {} received {} from {}", player_id.mention(), cfg.currency.fmt(payout), table.name);
+        let commission = cfg.tables.commission(payout);
+        commission_sum += commission;
+        let kind = TransactionKind::Payout { table: table_id, name: table.name.clone() };
+        cfg.transact(player_id, (payout - commission).try_into()?, kind)?;
+        tracing::info!(
+            "User {} received {} from {}",
+            player_id.mention(),
+            cfg.currency.fmt(payout - commission),
+            table.name
+        );
+    }
+    if commission_sum > 0 {
+        let kind = TransactionKind::Commission { table: table_id, name: table.name.clone() };
+        cfg.transact(table.dealer, commission_sum.try_into()?, kind)?;
+        tracing::info!("Dealer {} received {} commission", table.dealer, cfg.currency.fmt(commission_sum));
     }
 
-    if table.pot == 0 { Ok(cfg.gambling_tables.remove(&table_id).some()?) } else { Ok(table.clone()) }
+    if table.pot == 0 { Ok(cfg.gambling_tables.remove(&table_id).some()?) } else { Ok(table) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table() -> GamblingTable {
+        GamblingTable {
+            dealer: UserId::new(1),
+            name: "Table".to_string(),
+            description: None,
+            buyin: 50,
+            players: [(UserId::new(2), 50), (UserId::new(3), 50)].into(),
+            pot: 100,
+            last_activity: Utc::now(),
+            message: None,
+        }
+    }
+
+    #[test]
+    fn parse_amounts_percentages_and_rest() {
+        let payouts = parse_payout(&table(), "# comment\n2 alice: 25%\n<@3> bob: rest").unwrap();
+        assert_eq!(payouts, BTreeMap::from([(UserId::new(2), 25), (UserId::new(3), 75)]));
+
+        let payouts = parse_payout(&table(), "2 name: with: colons: 10\n2: 5").unwrap();
+        assert_eq!(payouts, BTreeMap::from([(UserId::new(2), 15)]));
+    }
+
+    #[test]
+    fn parse_reports_every_bad_line() {
+        let error = parse_payout(&table(), "2 alice: ten\n4 carol: 10\n3 bob: 10\nbob 10").unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Line 1: `ten` isn't an amount, a percentage or rest\n\
+             Line 2: `4 carol` isn't a player at this table\n\
+             Line 4: `bob 10` should look like `<id> <name>: <amount>`"
+        );
+
+        let error = parse_payout(&table(), "2 alice: 60\n3 bob: 60%\n2 alice: rest").unwrap_err().to_string();
+        assert_eq!(error, "The payouts add up to more than the pot: 120 > 100");
+    }
 }
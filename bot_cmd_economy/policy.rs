@@ -0,0 +1,293 @@
+use crate::ledger::TransactionKind;
+use crate::{ConfigT, Currency};
+use bot_core::{CmdContext, With};
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use eyre::{Result, ensure};
+use itertools::Itertools;
+use poise::CreateReply;
+use poise::serenity_prelude::{Colour, CreateEmbed, Mentionable as _, UserId};
+use std::collections::BTreeMap;
+
+/// Most money supply snapshots kept, one is taken per day
+const SUPPLY_HISTORY_LEN: usize = 365;
+
+/// Keeps the economy from inflating forever
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, sensible::Default)]
+pub(crate) struct MonetaryPolicy {
+    /// Account that collects fees, taxes and decay, the money leaves the economy if unset
+    pub(crate) bank: Option<UserId>,
+    /// How often the wealth tax and decay are applied
+    #[serde(with = "bot_core::serde::td_seconds")]
+    #[default(TimeDelta::days(7))]
+    period: TimeDelta,
+    /// Share of the balance above the threshold that's taxed every period, in percent
+    wealth_tax_percent: f64,
+    wealth_tax_threshold: u64,
+    /// Share of the balance that inactive accounts lose every period, in percent
+    inactivity_decay_percent: f64,
+    /// Accounts without transactions of their own or daily claims for this long are inactive
+    #[serde(with = "bot_core::serde::td_seconds")]
+    #[default(TimeDelta::days(30))]
+    inactive_after: TimeDelta,
+    /// When the wealth tax and decay were last applied
+    last_applied: Option<DateTime<Utc>>,
+    /// When the first period started, members without any activity count as active since then
+    started: Option<DateTime<Utc>>,
+}
+
+/// How much money there was at the start of a day
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct SupplySnapshot {
+    time: DateTime<Utc>,
+    /// Balances, table pots and market stakes
+    total: u64,
+    bank: u64,
+}
+
+impl ConfigT {
+    /// Takes money from the member and gives it to the bank, if there is one
+    pub(crate) fn collect(&mut self, user: UserId, amount: u64, kind: TransactionKind) -> Result<()> {
+        let what = match kind {
+            TransactionKind::TransferFee => "Transfer fee",
+            TransactionKind::WealthTax => "Wealth tax",
+            TransactionKind::InactivityDecay => "Inactivity decay",
+            _ => "Fee",
+        }
+        .to_string();
+        self.transact(user, -i64::try_from(amount)?, kind)?;
+        if let Some(bank) = self.policy.bank {
+            self.transact(bank, amount.try_into()?, TransactionKind::Collected { user, what })?;
+        }
+        Ok(())
+    }
+
+    /// All money in accounts, on gambling tables and in markets
+    fn money_supply(&self) -> u64 {
+        let balances = self.account.values().map(|a| a.balance).sum::<u64>();
+        let tables = self.gambling_tables.values().map(|t| t.pot).sum::<u64>();
+        let markets = self.markets.values().map(|m| m.pot()).sum::<u64>();
+        balances + tables + markets
+    }
+
+    fn bank_balance(&self) -> u64 {
+        self.policy.bank.and_then(|bank| self.account.get(&bank)).map_or(0, |a| a.balance)
+    }
+
+    /// When each member last did something, i.e. their latest transaction they caused themselves or daily claim
+    fn last_active(&self) -> BTreeMap<UserId, DateTime<Utc>> {
+        let mut last_active = self
+            .ledger
+            .iter()
+            .filter(|t| {
+                !matches!(
+                    t.kind,
+                    TransactionKind::WealthTax
+                        | TransactionKind::InactivityDecay
+                        | TransactionKind::TableRefund { .. }
+                        | TransactionKind::MarketRefund { .. }
+                        | TransactionKind::Adjustment
+                )
+            })
+            .map(|t| (t.user, t.time))
+            .collect::<BTreeMap<_, _>>();
+        for (&user, account) in &self.account {
+            if let Some(claim) = account.last_claim {
+                let last = last_active.entry(user).or_insert(claim);
+                *last = (*last).max(claim);
+            }
+        }
+        last_active
+    }
+
+    /// Collects the wealth tax and decays inactive accounts, returns the total collected
+    fn apply_policy(&mut self, now: DateTime<Utc>) -> Result<u64> {
+        let policy = self.policy.clone();
+        let last_active = self.last_active();
+        let mut collected = 0;
+
+        let accounts = self.account.iter().map(|(&user, a)| (user, a.balance)).collect_vec();
+        for (user, balance) in accounts.into_iter().filter(|&(user, _)| Some(user) != policy.bank) {
+            let tax = (balance.saturating_sub(policy.wealth_tax_threshold) as f64 * policy.wealth_tax_percent / 100.0)
+                .floor() as u64;
+            if tax > 0 {
+                self.collect(user, tax, TransactionKind::WealthTax)?;
+            }
+
+            let last = last_active.get(&user).copied().or(policy.started).unwrap_or(now);
+            let inactive = last + policy.inactive_after <= now;
+            let decay = if inactive {
+                ((balance - tax) as f64 * policy.inactivity_decay_percent / 100.0).floor() as u64
+            } else {
+                0
+            };
+            if decay > 0 {
+                self.collect(user, decay, TransactionKind::InactivityDecay)?;
+            }
+
+            collected += tax + decay;
+        }
+
+        Ok(collected)
+    }
+
+    fn recorded_money_supply_today(&self, now: DateTime<Utc>) -> bool {
+        let today = now.with_timezone(&Local).date_naive();
+        self.money_supply_history.last().is_some_and(|s| s.time.with_timezone(&Local).date_naive() == today)
+    }
+
+    /// Whether a period is over or the first one hasn't started yet
+    fn policy_due(&self, now: DateTime<Utc>) -> bool {
+        self.policy.started.is_none() || self.policy.last_applied.is_none_or(|last| last + self.policy.period <= now)
+    }
+
+    /// Records the money supply once per day
+    fn record_money_supply(&mut self, now: DateTime<Utc>) {
+        if self.recorded_money_supply_today(now) {
+            return;
+        }
+        let snapshot = SupplySnapshot { time: now, total: self.money_supply(), bank: self.bank_balance() };
+        self.money_supply_history.push(snapshot);
+        let excess = self.money_supply_history.len().saturating_sub(SUPPLY_HISTORY_LEN);
+        self.money_supply_history.drain(..excess);
+    }
+}
+
+/// Applies the monetary policy when a period is over and keeps track of the money supply
+pub(crate) async fn run_monetary_policy(data: &impl With<ConfigT>) -> Result<()> {
+    let now = Utc::now();
+    // most of the time there's nothing to do, so don't mark the config as changed
+    if data.with_ok(|cfg| cfg.recorded_money_supply_today(now) && !cfg.policy_due(now)).await? {
+        return Ok(());
+    }
+    let collected = data
+        .with_mut(|cfg| {
+            cfg.record_money_supply(now);
+            cfg.policy.started.get_or_insert(now);
+            let Some(last) = cfg.policy.last_applied else {
+                // the first period starts now
+                cfg.policy.last_applied = Some(now);
+                return Ok(None);
+            };
+            if last + cfg.policy.period > now {
+                return Ok(None);
+            }
+            cfg.policy.last_applied = Some(now);
+            cfg.apply_policy(now).map(Some)
+        })
+        .await?;
+
+    if let Some(collected) = collected {
+        tracing::info!("Applied monetary policy, collected {collected}");
+    }
+
+    Ok(())
+}
+
+/// See how much money is going around
+#[poise::command(slash_command, guild_only)]
+pub async fn money_supply<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[description = "How many days to show, defaults to 14"]
+    #[min = 1]
+    days: Option<u32>,
+) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let since = Utc::now() - TimeDelta::days(days.unwrap_or(14).into());
+    let (supply, bank_balance, policy, history) = ctx
+        .data()
+        .with_ok(|cfg| {
+            let history = cfg.money_supply_history.iter().filter(|s| s.time >= since).cloned().collect_vec();
+            (cfg.money_supply(), cfg.bank_balance(), cfg.policy.clone(), history)
+        })
+        .await?;
+    ensure!(supply > 0 || !history.is_empty(), "There's no money yet");
+
+    let mut embed = CreateEmbed::new().title("🏦 Money Supply").field("Total", cur.fmt(supply), true);
+    if let Some(bank) = policy.bank {
+        embed = embed.field("Bank", format!("{} {}", bank.mention(), cur.fmt(bank_balance)), true);
+    }
+
+    let mut previous = None;
+    let lines = history
+        .iter()
+        .map(|s| {
+            let change = previous.map(|previous: u64| {
+                let change = i64::try_from(i128::from(s.total) - i128::from(previous)).unwrap_or_default();
+                format!(" ({})", cur.fmt_signed(change))
+            });
+            previous = Some(s.total);
+            let bank = if policy.bank.is_some() { format!(" · 🏦 {}", cur.fmt(s.bank)) } else { String::new() };
+            format!("<t:{}:d> {}{}{bank}", s.time.timestamp(), cur.fmt(s.total), change.unwrap_or_default())
+        })
+        .join("\n");
+    if !lines.is_empty() {
+        embed = embed.description(lines);
+    }
+
+    let mut rules = vec![];
+    if policy.wealth_tax_percent > 0.0 {
+        rules.push(format!("{}% wealth tax above {}", policy.wealth_tax_percent, cur.fmt(policy.wealth_tax_threshold)));
+    }
+    if policy.inactivity_decay_percent > 0.0 {
+        rules.push(format!(
+            "{}% decay after {} days without activity",
+            policy.inactivity_decay_percent,
+            policy.inactive_after.num_days()
+        ));
+    }
+    if !rules.is_empty() {
+        let next = policy.last_applied.map(|last| format!("\nNext <t:{}:R>", (last + policy.period).timestamp()));
+        embed = embed.field("Policy", rules.join("\n") + &next.unwrap_or_default(), false);
+    }
+
+    ctx.send(CreateReply::new().embed(embed.colour(Colour::DARK_GOLD))).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::UserAccount;
+    use uuid::Uuid;
+
+    #[test]
+    fn tax_and_decay_go_to_the_bank() {
+        let (rich, idle, bank) = (UserId::new(1), UserId::new(2), UserId::new(3));
+        let now = Utc::now();
+        let mut cfg = ConfigT::default();
+        cfg.policy.bank = Some(bank);
+        cfg.policy.wealth_tax_percent = 10.0;
+        cfg.policy.wealth_tax_threshold = 1000;
+        cfg.policy.inactivity_decay_percent = 10.0;
+        cfg.policy.started = Some(now - TimeDelta::days(60));
+        cfg.account.insert(rich, UserAccount { balance: 1500, last_claim: None });
+        cfg.account.insert(idle, UserAccount { balance: 100, last_claim: None });
+        cfg.transact(rich, 0, TransactionKind::Activity).unwrap();
+        // refunds don't count as activity
+        let refund = TransactionKind::TableRefund { table: Uuid::new_v4(), name: "Table".to_string() };
+        cfg.transact(idle, 0, refund).unwrap();
+
+        assert_eq!(cfg.apply_policy(now).unwrap(), 60);
+        assert_eq!(cfg.account[&rich].balance, 1450);
+        assert_eq!(cfg.account[&idle].balance, 90);
+        assert_eq!(cfg.bank_balance(), 60);
+        assert_eq!(cfg.money_supply(), 1600);
+    }
+
+    #[test]
+    fn daily_claims_and_the_policy_start_count_as_activity() {
+        let (claimer, newcomer) = (UserId::new(1), UserId::new(2));
+        let now = Utc::now();
+        let mut cfg = ConfigT::default();
+        cfg.policy.inactivity_decay_percent = 10.0;
+        cfg.policy.started = Some(now - TimeDelta::days(10));
+        cfg.account.insert(claimer, UserAccount { balance: 100, last_claim: Some(now - TimeDelta::days(1)) });
+        cfg.account.insert(newcomer, UserAccount { balance: 100, last_claim: None });
+
+        assert_eq!(cfg.apply_policy(now).unwrap(), 0);
+        // once the policy has run for longer than the inactivity period, accounts without activity decay
+        assert_eq!(cfg.apply_policy(now + TimeDelta::days(25)).unwrap(), 10);
+        assert_eq!(cfg.account[&newcomer].balance, 90);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::ledger::TransactionKind;
+use crate::{ConfigT, Currency};
+use bot_core::{CmdContext, State, With};
+use chrono::{DateTime, TimeDelta, Utc};
+use eyre::{OptionExt as _, Result, bail, ensure};
+use itertools::Itertools;
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    AutocompleteChoice, Builder as _, Colour, Context, CreateAutocompleteResponse, CreateEmbed, GuildId,
+    Mentionable as _, RoleId, UserId,
+};
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct ShopItem {
+    description: Option<String>,
+    price: u64,
+    grant: Grant,
+    /// How many are left, unlimited if unset
+    stock: Option<u64>,
+    /// How long a member has to wait before buying the item again
+    #[serde(default, with = "bot_core::serde::td_seconds")]
+    cooldown: TimeDelta,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Grant {
+    /// Gives the role, for a number of days if set
+    Role { role: RoleId, days: Option<u32> },
+    /// A message the buyer writes, played with TTS when they join a voice channel
+    TtsJoinMessage { max_length: usize },
+    /// Sets the icon of the role to an emoji the buyer picks
+    RoleIcon { role: RoleId },
+}
+
+impl Grant {
+    fn describe(&self) -> String {
+        match self {
+            Grant::Role { role, days: Some(days) } => format!("{} for {days} days", role.mention()),
+            Grant::Role { role, days: None } => role.mention().to_string(),
+            Grant::TtsJoinMessage { max_length } => {
+                format!("A TTS message when you join voice, {max_length} characters")
+            }
+            Grant::RoleIcon { role } => format!("Pick the icon of {}", role.mention()),
+        }
+    }
+}
+
+/// A role bought in the shop that's taken away again when it expires
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct TimedRole {
+    user: UserId,
+    role: RoleId,
+    expires: DateTime<Utc>,
+}
+
+impl ConfigT {
+    /// When the user last bought the item, ignoring refunded purchases
+    fn last_purchase(&self, user: UserId, name: &str) -> Option<DateTime<Utc>> {
+        let mut refunds = 0;
+        for transaction in self.ledger.iter().rev().filter(|t| t.user == user) {
+            match &transaction.kind {
+                TransactionKind::Refund { item } if item == name => refunds += 1,
+                TransactionKind::Purchase { item } if item == name && refunds > 0 => refunds -= 1,
+                TransactionKind::Purchase { item } if item == name => return Some(transaction.time),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn purchase(&mut self, cur: &Currency, user: UserId, name: &str) -> Result<ShopItem> {
+        let item = self.shop.get(name).ok_or_eyre("There's no such item in the shop")?.clone();
+        ensure!(item.stock != Some(0), "{name} is sold out");
+        if let Some(last) = self.last_purchase(user, name) {
+            let next = last + item.cooldown;
+            ensure!(next <= Utc::now(), "You can buy {name} again <t:{}:R>", next.timestamp());
+        }
+        let balance = self.account.get(&user).map_or(0, |a| a.balance);
+        ensure!(balance >= item.price, "You don't have enough money: {} < {}", cur.fmt(balance), cur.fmt(item.price));
+
+        self.transact(user, -i64::try_from(item.price)?, TransactionKind::Purchase { item: name.to_string() })?;
+        if let Some(stock) = &mut self.shop.get_mut(name).ok_or_eyre("There's no such item in the shop")?.stock {
+            *stock -= 1;
+        }
+        Ok(item)
+    }
+
+    fn refund(&mut self, user: UserId, name: &str, item: &ShopItem) -> Result<()> {
+        self.transact(user, item.price.try_into()?, TransactionKind::Refund { item: name.to_string() })?;
+        if let Some(stock) = self.shop.get_mut(name).and_then(|item| item.stock.as_mut()) {
+            *stock += 1;
+        }
+        Ok(())
+    }
+}
+
+/// See what you can buy
+#[poise::command(slash_command, guild_only)]
+pub async fn shop<D: With<ConfigT>>(ctx: CmdContext<'_, D>) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let items = ctx.data().with_ok(|cfg| cfg.shop.clone()).await?;
+    ensure!(!items.is_empty(), "The shop is empty");
+
+    let mut embed = CreateEmbed::new().title("🛒 Shop").colour(Colour::GOLD);
+    for (name, item) in &items {
+        let mut value = format!("{}\n{}", cur.fmt(item.price), item.grant.describe());
+        if let Some(description) = &item.description {
+            value = format!("{description}\n{value}");
+        }
+        if let Some(stock) = item.stock {
+            value += &format!("\n{stock} left");
+        }
+        embed = embed.field(name, value, false);
+    }
+    ctx.send(CreateReply::new().embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Buy something from the shop
+#[poise::command(slash_command, guild_only)]
+pub async fn buy<D: With<ConfigT> + With<bot_cmd_tts::ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[autocomplete = shop_items]
+    #[description = "The item to buy"]
+    item: String,
+    #[description = "The TTS message or emoji, if the item needs one"] text: Option<String>,
+) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let user = ctx.author().id;
+    let guild_id = ctx.guild_id().ok_or_eyre("Not in a guild")?;
+
+    // check the input before anything is paid
+    let grant = ctx
+        .data()
+        .with(|cfg: &ConfigT| Ok(cfg.shop.get(&item).ok_or_eyre("There's no such item in the shop")?.grant.clone()))
+        .await?;
+    let icon_edit = match (&grant, &text) {
+        (Grant::TtsJoinMessage { max_length }, Some(text)) => {
+            ensure!(text.chars().count() <= *max_length, "The message can be at most {max_length} characters long");
+            None
+        }
+        (Grant::RoleIcon { .. }, Some(text)) => Some(
+            bot_cmd_role_icon::icon_edit(ctx.serenity_context(), text.trim())
+                .await?
+                .ok_or_eyre("That's not an emoji")?,
+        ),
+        (Grant::TtsJoinMessage { .. }, None) => bail!("Enter the message you want to hear as text"),
+        (Grant::RoleIcon { .. }, None) => bail!("Enter the emoji you want as text"),
+        (Grant::Role { .. }, _) => None,
+    };
+    if let Grant::Role { role, .. } = grant {
+        // otherwise a timed purchase would take away a role the member had before
+        let has_role = ctx.author_member().await.is_some_and(|member| member.roles.contains(&role));
+        let timed = ctx
+            .data()
+            .with_ok(|cfg: &ConfigT| cfg.timed_roles.iter().any(|t| t.user == user && t.role == role))
+            .await?;
+        ensure!(!has_role || timed, "You already have {}", role.mention());
+    }
+
+    let bought = ctx.data().with_mut(|cfg: &mut ConfigT| cfg.purchase(&cur, user, &item)).await?;
+
+    let granted = async {
+        match bought.grant.clone() {
+            Grant::Role { role, days } => {
+                ctx.http().add_member_role(guild_id, user, role, Some(&format!("Bought {item}"))).await?;
+                ctx.data()
+                    .with_mut_ok(|cfg: &mut ConfigT| {
+                        let timed = cfg.timed_roles.iter().position(|t| t.user == user && t.role == role);
+                        match (days, timed) {
+                            // buying a role you already have extends it
+                            (Some(days), Some(i)) => {
+                                let timed = &mut cfg.timed_roles[i];
+                                timed.expires = timed.expires.max(Utc::now()) + TimeDelta::days(days.into());
+                            }
+                            (Some(days), None) => cfg.timed_roles.push(TimedRole {
+                                user,
+                                role,
+                                expires: Utc::now() + TimeDelta::days(days.into()),
+                            }),
+                            // it doesn't expire anymore
+                            (None, Some(i)) => _ = cfg.timed_roles.remove(i),
+                            (None, None) => {}
+                        }
+                    })
+                    .await?;
+            }
+            Grant::TtsJoinMessage { .. } => {
+                let message = text.clone().ok_or_eyre("No message")?;
+                bot_cmd_tts::add_join_message(ctx.data(), user, message).await?;
+            }
+            Grant::RoleIcon { role } => {
+                let edit = icon_edit.ok_or_eyre("No emoji")?;
+                edit.execute(ctx.serenity_context(), (guild_id, Some(role))).await?;
+            }
+        }
+        eyre::Ok(())
+    }
+    .await;
+
+    if let Err(error) = granted {
+        tracing::warn!("Refunding {item} to {user}: {error:?}");
+        ctx.data().with_mut(|cfg: &mut ConfigT| cfg.refund(user, &item, &bought)).await?;
+        bail!("Couldn't give you {item}, your money was refunded: {error}");
+    }
+
+    tracing::info!("User {user} bought {item} for {}", cur.fmt(bought.price));
+    let embed = CreateEmbed::new()
+        .title(format!("🛒 Bought {item}"))
+        .description(bought.grant.describe())
+        .field("Price", cur.fmt(bought.price), true)
+        .colour(Colour::DARK_GREEN);
+    ctx.send(CreateReply::new().embed(embed)).await?;
+
+    Ok(())
+}
+
+async fn shop_items<U, E>(ctx: poise::Context<'_, U, E>, input: &str) -> CreateAutocompleteResponse
+where
+    U: With<ConfigT>,
+{
+    let names = ctx.data().with_ok(|cfg| cfg.shop.keys().cloned().collect_vec()).await.unwrap_or_default();
+    let choices = names
+        .into_iter()
+        .filter(|name| name.to_lowercase().contains(&input.to_lowercase()))
+        .map(|name| AutocompleteChoice::new(name.clone(), name))
+        .take(25)
+        .collect();
+    CreateAutocompleteResponse::new().set_choices(choices)
+}
+
+pub(crate) async fn remove_expired_roles(ctx: &Context, data: &(impl With<ConfigT> + State<GuildId>)) -> Result<()> {
+    let guild_id: GuildId = *data.state();
+    let now = Utc::now();
+    if !data.with_ok(|cfg| cfg.timed_roles.iter().any(|timed| timed.expires <= now)).await? {
+        return Ok(());
+    }
+    let expired =
+        data.with_mut_ok(|cfg| cfg.timed_roles.extract_if(.., |timed| timed.expires <= now).collect_vec()).await?;
+
+    for TimedRole { user, role, .. } in expired {
+        tracing::info!("Removing expired role {role} from {user}");
+        // the member may have left or the role may be gone, the entry is dropped either way
+        if let Err(error) = ctx.http.remove_member_role(guild_id, user, role, Some("Bought role expired")).await {
+            tracing::warn!("Failed to remove role {role} from {user}: {error}");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use crate::ledger::TransactionKind;
+use crate::pay_out::apply_payout;
+use crate::{ConfigT, Currency, GamblingTable};
+use bot_core::{CmdContext, With};
+use chrono::{TimeDelta, Utc};
+use eyre::{OptionExt as _, Result, ensure};
+use itertools::Itertools;
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    AutocompleteChoice, Colour, Context, CreateAutocompleteResponse, CreateEmbed, EditMessage, Mentionable as _, UserId,
+};
+use uuid::Uuid;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, sensible::Default)]
+pub(crate) struct TableConfig {
+    /// Tables without buy-ins or payouts for this long are closed and the bets refunded
+    #[serde(with = "bot_core::serde::td_seconds")]
+    #[default(TimeDelta::days(7))]
+    expiry: TimeDelta,
+    /// Share of every payout that goes to the dealer
+    pub(crate) dealer_commission_percent: f64,
+}
+
+impl TableConfig {
+    /// The dealer's share of a payout
+    pub(crate) fn commission(&self, payout: u64) -> u64 {
+        ((payout as f64 * self.dealer_commission_percent / 100.0).floor() as u64).min(payout)
+    }
+}
+
+impl GamblingTable {
+    /// Removes the buttons from the table's message once it's closed
+    pub(crate) async fn deactivate_message(&self, ctx: &Context, cur: &Currency) {
+        let Some((channel, message)) = self.message else { return };
+        let edit = EditMessage::new().embed(self.embed(cur).colour(Colour::DARKER_GREY)).components(vec![]);
+        // the message may have been deleted, the table is closed either way
+        if let Err(error) = channel.edit_message(ctx, message, edit).await {
+            tracing::warn!("Failed to update the message of table {}: {error}", self.name);
+        }
+    }
+
+    /// What the players get back when the table is closed.
+    /// The bets are scaled to the pot and anything left over goes to the biggest bet, or the dealer if nobody's left.
+    fn refunds(&self) -> Vec<(UserId, u64)> {
+        let bets = self.players.values().sum::<u64>();
+        let mut refunds = self
+            .players
+            .iter()
+            .map(|(&user, &bet)| {
+                let refund = u128::from(bet) * u128::from(self.pot) / u128::from(bets.max(self.pot).max(1));
+                (user, refund as u64)
+            })
+            .collect_vec();
+
+        let rest = self.pot - refunds.iter().map(|x| x.1).sum::<u64>();
+        if rest > 0 {
+            match refunds.iter_mut().max_by_key(|(_, refund)| *refund) {
+                Some(biggest) => biggest.1 += rest,
+                None => refunds.push((self.dealer, rest)),
+            }
+        }
+        refunds
+    }
+}
+
+impl ConfigT {
+    /// Refunds the bets and removes the table
+    fn refund_table(&mut self, table_id: Uuid) -> Result<(GamblingTable, Vec<(UserId, u64)>)> {
+        let table = self.gambling_tables.remove(&table_id).ok_or_eyre("Table doesn't exist")?;
+        let refunds = table.refunds();
+        for &(user, refund) in &refunds {
+            let kind = TransactionKind::TableRefund { table: table_id, name: table.name.clone() };
+            self.transact(user, refund.try_into()?, kind)?;
+        }
+        Ok((table, refunds))
+    }
+}
+
+/// Manage gambling tables
+#[poise::command(slash_command, guild_only, subcommands("table_close"), subcommand_required)]
+pub async fn table<D: With<ConfigT>>(_ctx: CmdContext<'_, D>) -> Result<()> {
+    Ok(())
+}
+
+/// Close a gambling table, refunding the bets or paying out the whole pot
+#[poise::command(
+    slash_command,
+    guild_only,
+    rename = "close",
+    required_permissions = "MODERATE_MEMBERS",
+    default_member_permissions = "MODERATE_MEMBERS"
+)]
+pub async fn table_close<D: With<ConfigT>>(
+    ctx: CmdContext<'_, D>,
+    #[autocomplete = gambling_tables]
+    #[description = "The table to close"]
+    table: String,
+    #[description = "Player who gets the whole pot, the bets are refunded if unset"] winner: Option<UserId>,
+) -> Result<()> {
+    let cur = Currency::read(ctx.data()).await?;
+    let table_id = Uuid::try_parse(&table)?;
+
+    let (table, payouts, commission) = ctx
+        .data()
+        .with_mut(|cfg| {
+            let table = cfg.gambling_tables.get(&table_id).ok_or_eyre("Table doesn't exist")?.clone();
+            match winner {
+                Some(winner) => {
+                    ensure!(table.players.contains_key(&winner), "{} isn't playing at this table", winner.mention());
+                    apply_payout(cfg, table_id, &[(winner, table.pot)])?;
+                    // the winner gets the pot minus the dealer's commission, like any other payout
+                    let commission = cfg.tables.commission(table.pot);
+                    let payouts = vec![(winner, table.pot - commission)];
+                    Ok((table, payouts, commission))
+                }
+                None => cfg.refund_table(table_id).map(|(table, refunds)| (table, refunds, 0)),
+            }
+        })
+        .await?;
+    tracing::info!("{} closed table {}", ctx.author().id, table.name);
+    table.deactivate_message(ctx.serenity_context(), &cur).await;
+
+    let title = format!("{} was closed", table.name);
+    let summary = payouts.iter().map(|&(user, amount)| format!("{} {}", user.mention(), cur.fmt(amount))).join("\n");
+    let mut embed = CreateEmbed::new()
+        .title(title)
+        .field(if winner.is_some() { "Paid out" } else { "Refunded" }, summary, false)
+        .colour(Colour::DARKER_GREY);
+    if commission > 0 {
+        embed = embed.field("Dealer commission", format!("{} {}", table.dealer.mention(), cur.fmt(commission)), false);
+    }
+    ctx.send(CreateReply::new().embed(embed)).await?;
+
+    Ok(())
+}
+
+async fn gambling_tables<U, E>(ctx: poise::Context<'_, U, E>, input: &str) -> CreateAutocompleteResponse
+where
+    U: With<ConfigT>,
+{
+    let tables = ctx
+        .data()
+        .with_ok(|cfg| cfg.gambling_tables.iter().map(|(id, table)| (*id, table.name.clone())).collect_vec())
+        .await
+        .unwrap_or_default();
+    let choices = tables
+        .into_iter()
+        .filter(|(_, name)| name.to_lowercase().contains(&input.to_lowercase()))
+        .map(|(id, name)| AutocompleteChoice::new(name, id.to_string()))
+        .take(25)
+        .collect();
+    CreateAutocompleteResponse::new().set_choices(choices)
+}
+
+/// Closes tables that nobody used for too long and refunds their bets
+pub(crate) async fn close_expired_tables(ctx: &Context, data: &impl With<ConfigT>) -> Result<()> {
+    let expired = data
+        .with_ok(|cfg| {
+            let expired_before = Utc::now() - cfg.tables.expiry;
+            cfg.gambling_tables
+                .iter()
+                .filter(|(_, table)| table.last_activity < expired_before)
+                .map(|(&id, _)| id)
+                .collect_vec()
+        })
+        .await?;
+    if expired.is_empty() {
+        return Ok(());
+    }
+
+    let cur = Currency::read(data).await?;
+    let closed = data
+        .with_mut(|cfg| {
+            let mut closed = vec![];
+            // a table may have been closed in the meantime
+            for id in expired.into_iter().filter(|id| cfg.gambling_tables.contains_key(id)).collect_vec() {
+                closed.push(cfg.refund_table(id)?.0);
+            }
+            Ok(closed)
+        })
+        .await?;
+
+    for table in closed {
+        tracing::info!("Closed expired table {}", table.name);
+        table.deactivate_message(ctx, &cur).await;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table(pot: u64, players: &[(u64, u64)]) -> GamblingTable {
+        GamblingTable {
+            dealer: UserId::new(1),
+            name: "Table".to_string(),
+            description: None,
+            buyin: 10,
+            players: players.iter().map(|&(user, bet)| (UserId::new(user), bet)).collect(),
+            pot,
+            last_activity: Utc::now(),
+            message: None,
+        }
+    }
+
+    #[test]
+    fn refunds_cover_the_pot() {
+        let refunds = |table: GamblingTable| table.refunds().into_iter().map(|(u, r)| (u.get(), r)).collect_vec();
+        assert_eq!(refunds(table(30, &[(2, 10), (3, 20)])), [(2, 10), (3, 20)]);
+        // earlier payouts took more than those players bet
+        assert_eq!(refunds(table(15, &[(2, 10), (3, 20)])), [(2, 5), (3, 10)]);
+        // earlier payouts took less than those players bet
+        assert_eq!(refunds(table(40, &[(2, 10), (3, 20)])), [(2, 10), (3, 30)]);
+        assert_eq!(refunds(table(7, &[])), [(1, 7)]);
+    }
+}
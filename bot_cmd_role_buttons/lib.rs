@@ -1,12 +1,12 @@
+use bot_core::component_store::ComponentStore;
 use bot_core::ext::create_reply::CreateReplyExt;
 use bot_core::ext::option::OptionExt as _;
-use bot_core::{EvtContext, UserData, With};
+use bot_core::{EvtContext, State, UserData, With};
 use eyre::{Context as _, OptionExt as _, Result, bail, ensure};
-use itertools::Itertools;
 use poise::CreateReply;
 use poise::serenity_prelude::{
     ComponentInteraction, ComponentInteractionDataKind, CreateActionRow, CreateSelectMenu, CreateSelectMenuKind,
-    CreateSelectMenuOption, ReactionType, RoleId,
+    CreateSelectMenuOption, InteractionId, ReactionType, RoleId,
 };
 use std::collections::{BTreeMap, HashSet};
 
@@ -18,6 +18,11 @@ pub struct ConfigT {
     buttons: BTreeMap<String, RoleButtonData>,
 }
 
+#[derive(Default)]
+pub struct StateT {
+    offered_roles: ComponentStore<Vec<RoleId>>,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 struct RoleButtonData {
     on_click: Option<RoleId>,
@@ -33,7 +38,7 @@ struct RoleData {
 }
 
 pub async fn btn_show_role_selection(
-    ctx: EvtContext<'_, impl With<ConfigT>>,
+    ctx: EvtContext<'_, impl With<ConfigT> + State<StateT>>,
     interaction: &ComponentInteraction,
     param: &str,
 ) -> Result<()> {
@@ -43,7 +48,7 @@ pub async fn btn_show_role_selection(
 
     let role_button = read_role_button_data(ctx.user_data, role_set_id).await?;
 
-    let options = {
+    let (offered, options): (Vec<_>, Vec<_>) = {
         let guild = ctx.serenity_context.cache.guild(guild_id).some()?;
         let member = guild.members.get(&user_id).ok_or_eyre("No member")?;
         let member_roles: HashSet<RoleId> = member.roles.iter().copied().collect();
@@ -52,20 +57,25 @@ pub async fn btn_show_role_selection(
             .into_iter()
             .filter_map(|role_config| Some((guild.roles.get(&role_config.role_id)?, role_config)))
             .map(|(role, role_config)| {
-                CreateSelectMenuOption::new(role.name.clone(), role_config.role_id.get().to_string())
+                let option = CreateSelectMenuOption::new(role.name.clone(), role_config.role_id.get().to_string())
                     .description(role_config.description)
                     .emoji(role_config.emoji)
-                    .default_selection(member_roles.contains(&role_config.role_id))
+                    .default_selection(member_roles.contains(&role_config.role_id));
+                (role_config.role_id, option)
             })
-            .collect_vec()
+            .unzip()
     };
+    ctx.user_data.state().offered_roles.insert(interaction.id, offered);
 
     let max_values = options.len() as u8;
     CreateReply::new()
         .components(vec![CreateActionRow::SelectMenu(
-            CreateSelectMenu::new(format!("{SELECT_ID}:{role_set_id}"), CreateSelectMenuKind::String { options })
-                .min_values(0)
-                .max_values(max_values),
+            CreateSelectMenu::new(
+                format!("{SELECT_ID}:{}:{role_set_id}", interaction.id),
+                CreateSelectMenuKind::String { options },
+            )
+            .min_values(0)
+            .max_values(max_values),
         )])
         .ephemeral(true)
         .respond_to_component(ctx.serenity_context, interaction)
@@ -83,11 +93,12 @@ pub async fn btn_show_role_selection(
 }
 
 pub async fn select_roles(
-    ctx: EvtContext<'_, impl With<ConfigT>>,
+    ctx: EvtContext<'_, impl With<ConfigT> + State<StateT>>,
     interaction: &ComponentInteraction,
     param: &str,
 ) -> Result<()> {
-    let role_set_id = param;
+    let (key, role_set_id) = param.split_once(':').ok_or_eyre("Invalid role selection")?;
+    let offered = ctx.user_data.state().offered_roles.get(key.parse::<InteractionId>()?)?;
     let user_id = interaction.user.id;
     let guild_id = interaction.guild_id.some()?;
     let ComponentInteractionDataKind::StringSelect { values } = interaction.data.kind.clone() else {
@@ -97,7 +108,9 @@ pub async fn select_roles(
     interaction.defer(ctx.serenity_context).await?;
 
     let role_button = read_role_button_data(ctx.user_data, role_set_id).await?;
-    let selectable: HashSet<_> = role_button.roles.iter().map(|r| r.role_id).collect();
+    // Only touch roles that were shown in the menu and are still configured
+    let selectable: HashSet<_> =
+        role_button.roles.iter().map(|r| r.role_id).filter(|role_id| offered.contains(role_id)).collect();
 
     let selected: HashSet<_> = values.into_iter().filter_map(|s| s.parse().ok()).collect();
     let selected: HashSet<_> = selected.intersection(&selectable).collect();
@@ -1,6 +1,6 @@
 use bot_core::{EvtContext, With};
 use eyre::Result;
-use poise::serenity_prelude::{Builder, CreateAttachment, EditRole, Message, RoleId, UserId, parse_emoji};
+use poise::serenity_prelude::{Builder, Context, CreateAttachment, EditRole, Message, RoleId, UserId, parse_emoji};
 use rand::RngExt;
 use std::collections::BTreeMap;
 
@@ -41,16 +41,7 @@ pub async fn on_message<D: With<ConfigT>>(ctx: EvtContext<'_, D>, message: &Mess
     }
 
     for word in message.content.split_ascii_whitespace() {
-        let edit_role = {
-            if let Some(emoji) = parse_emoji(word) {
-                let icon = CreateAttachment::url(ctx.serenity_context, &emoji.url()).await?;
-                EditRole::default().icon(Some(&icon))
-            } else if emojis::get(word).is_some() {
-                EditRole::default().unicode_emoji(Some(word.to_string()))
-            } else {
-                continue;
-            }
-        };
+        let Some(edit_role) = icon_edit(ctx.serenity_context, word).await? else { continue };
 
         for role_id in roles {
             edit_role.clone().execute(ctx.serenity_context, (guild_id, Some(role_id))).await?;
@@ -61,3 +52,15 @@ pub async fn on_message<D: With<ConfigT>>(ctx: EvtContext<'_, D>, message: &Mess
 
     Ok(())
 }
+
+/// An edit that sets a role's icon to the emoji, or `None` if the word isn't an emoji
+pub async fn icon_edit(ctx: &Context, word: &str) -> Result<Option<EditRole<'static>>> {
+    Ok(if let Some(emoji) = parse_emoji(word) {
+        let icon = CreateAttachment::url(ctx, &emoji.url()).await?;
+        Some(EditRole::default().icon(Some(&icon)))
+    } else if emojis::get(word).is_some() {
+        Some(EditRole::default().unicode_emoji(Some(word.to_string())))
+    } else {
+        None
+    })
+}
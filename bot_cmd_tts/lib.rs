@@ -1,13 +1,11 @@
 #![feature(trait_alias)]
 #![allow(clippy::mutable_key_type)]
 
-mod tts;
-
 use bot_core::audio::{Playable, play};
 use bot_core::ext::option::OptionExt as _;
 use bot_core::serde::LiteralRegex;
 use bot_core::voice_change::VoiceChange;
-use bot_core::{EvtContext, State, With, hash_store, template};
+use bot_core::{EvtContext, State, With, hash_store, template, tts};
 use dashmap::DashMap;
 use eyre::{OptionExt as _, Result, bail};
 use itertools::Itertools;
@@ -236,6 +234,11 @@ async fn get_clips(chttp: impl CacheHttp, data: &impl With<ConfigT>) -> Result<H
     Ok(clips)
 }
 
+/// Adds a message that can be played when the user joins a voice channel
+pub async fn add_join_message(data: &impl With<ConfigT>, user_id: UserId, message: String) -> Result<()> {
+    data.with_mut_ok(|cfg| cfg.join.user_messages.entry(user_id).or_default().push(message)).await
+}
+
 pub async fn setup(ctx: Context, data: impl With<ConfigT>) -> Result<()> {
     tracing::debug!("Pre-fetching TTS clips");
     get_clips(&ctx, &data).await?;
@@ -1,4 +1,7 @@
-use chrono::Timelike;
+use crate::time::human::{DAY_WORDS, parse_date, parse_datetime};
+use crate::time::local_timezone;
+use chrono::{DateTime, TimeDelta, Timelike, Utc};
+use chrono_tz::Tz;
 use itertools::Itertools as _;
 use poise::serenity_prelude::{AutocompleteChoice, CreateAutocompleteResponse};
 
@@ -31,3 +34,72 @@ pub async fn time<U, E>(_ctx: poise::Context<'_, U, E>, input: &str) -> CreateAu
 
     CreateAutocompleteResponse::new().set_choices(choices)
 }
+
+/// Completes the last word of a date and time expression, and shows how the whole input is understood
+pub async fn datetime<U, E>(_ctx: poise::Context<'_, U, E>, input: &str) -> CreateAutocompleteResponse {
+    datetime_in(input, local_timezone(), Utc::now())
+}
+
+/// Like [`datetime`], for commands that parse the input in another timezone than the server's
+pub fn datetime_in(input: &str, tz: Tz, now: DateTime<Utc>) -> CreateAutocompleteResponse {
+    let (prefix, last) = match input.rsplit_once(' ') {
+        Some((prefix, last)) => (format!("{prefix} "), last),
+        None => (String::new(), input),
+    };
+
+    let mut times: Vec<_> = (0..=23).cartesian_product([0, 15, 30, 45]).collect();
+    let local_now = now.with_timezone(&tz);
+    times.rotate_left(local_now.hour() as usize * 4 + local_now.minute() as usize / 15 + 1);
+    let completions = DAY_WORDS
+        .into_iter()
+        .map(|day| format!("{day} "))
+        .chain(times.into_iter().map(|(h, m)| format!("{h:02}:{m:02}")))
+        .filter(|word| word.starts_with(&last.to_lowercase()))
+        .map(|word| format!("{prefix}{word}"));
+
+    let choices = std::iter::once(input.to_string())
+        .chain(completions)
+        // Discord rejects the whole response if a name or value is longer than 100 characters
+        .filter(|input| !input.trim().is_empty() && input.chars().count() <= 100)
+        .unique()
+        .map(|input| match parse_datetime(&input, &tz, now) {
+            Some(dt) => {
+                let preview = format!(" → {}", dt.format("%a %d.%m. %H:%M"));
+                let shown = input.chars().take(100 - preview.chars().count()).collect::<String>();
+                AutocompleteChoice::new(format!("{shown}{preview}"), input)
+            }
+            None => AutocompleteChoice::new(input.clone(), input),
+        })
+        .take(25)
+        .collect();
+
+    CreateAutocompleteResponse::new().set_choices(choices)
+}
+
+/// Suggests the next two weeks, or the date that day words like "tomorrow" or "fri" stand for
+pub async fn date<U, E>(_ctx: poise::Context<'_, U, E>, input: &str) -> CreateAutocompleteResponse {
+    let today = Utc::now().with_timezone(&local_timezone()).date_naive();
+    let dates = match parse_date(input, today) {
+        Some(date) => vec![date],
+        None => (0..14).map(|days| today + TimeDelta::days(days)).collect(),
+    };
+    let choices = dates
+        .into_iter()
+        .map(|date| AutocompleteChoice::new(date.format("%A, %d.%m.%Y").to_string(), date.to_string()))
+        .collect();
+
+    CreateAutocompleteResponse::new().set_choices(choices)
+}
+
+pub async fn timezone<U, E>(_ctx: poise::Context<'_, U, E>, input: &str) -> CreateAutocompleteResponse {
+    let input = input.to_lowercase();
+    let choices = chrono_tz::TZ_VARIANTS
+        .iter()
+        .map(|tz| tz.name())
+        .filter(|name| name.to_lowercase().contains(&input))
+        .map(|name| AutocompleteChoice::new(name, name))
+        .take(25)
+        .collect();
+
+    CreateAutocompleteResponse::new().set_choices(choices)
+}
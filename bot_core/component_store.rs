@@ -0,0 +1,59 @@
+use dashmap::DashMap;
+use eyre::{OptionExt as _, Result};
+use poise::serenity_prelude::InteractionId;
+use std::time::Duration;
+use tokio::time::Instant;
+
+const EXPIRY: Duration = Duration::from_secs(60 * 60);
+
+/// Server-side state of message components, e.g. which options a select menu offered.
+/// Entries are keyed by the interaction that sent the components, put that id into their custom ids
+/// so the handlers can look the state up again. Entries expire after an hour.
+#[derive(Debug)]
+pub struct ComponentStore<T>(DashMap<InteractionId, (Instant, T)>);
+
+impl<T> Default for ComponentStore<T> {
+    fn default() -> Self {
+        Self(DashMap::new())
+    }
+}
+
+impl<T: Clone> ComponentStore<T> {
+    pub fn insert(&self, key: InteractionId, value: T) {
+        let now = Instant::now();
+        self.0.retain(|_, (expires_at, _)| *expires_at > now);
+        self.0.insert(key, (now + EXPIRY, value));
+    }
+
+    pub fn get(&self, key: InteractionId) -> Result<T> {
+        self.0
+            .get(&key)
+            .filter(|entry| entry.0 > Instant::now())
+            .map(|entry| entry.1.clone())
+            .ok_or_eyre("This menu has expired, please open it again")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn get_returns_inserted_value() {
+        let store = ComponentStore::default();
+        store.insert(InteractionId::new(1), "roles");
+        assert_eq!(store.get(InteractionId::new(1)).unwrap(), "roles");
+        assert!(store.get(InteractionId::new(2)).is_err());
+    }
+
+    #[test]
+    fn expired_entries_are_gone() {
+        let store = ComponentStore::default();
+        store.0.insert(InteractionId::new(1), (Instant::now(), "old"));
+        assert!(store.get(InteractionId::new(1)).is_err());
+
+        // inserting cleans up expired entries
+        store.insert(InteractionId::new(2), "new");
+        assert!(!store.0.contains_key(&InteractionId::new(1)));
+    }
+}
@@ -2,7 +2,7 @@ use eyre::Result;
 use poise::CreateReply;
 use poise::serenity_prelude::{
     Builder as _, CommandInteraction, ComponentInteraction, Context, CreateAttachment, CreateEmbed,
-    CreateInteractionResponse, Message, ModalInteraction,
+    CreateInteractionResponse, Message, MessageFlags, ModalInteraction,
 };
 
 // todo generalize ComponentInteraction and ModalInteraction
@@ -17,6 +17,8 @@ pub trait CreateReplyExt {
     async fn update_to_component(self, ctx: &Context, interaction: &ComponentInteraction) -> Result<()>;
     async fn edit_initial_modal_response(self, ctx: &Context, interaction: &ModalInteraction) -> Result<Message>;
     async fn edit_message(self, ctx: &Context, message: &Message) -> Result<Message>;
+    /// Edits the message of a deferred component or modal interaction, ephemeral ones can only be edited through it
+    async fn edit_interaction_message(self, ctx: &Context, message: &Message, token: &str) -> Result<Message>;
 }
 
 #[async_trait::async_trait]
@@ -71,4 +73,12 @@ impl CreateReplyExt for CreateReply {
             .execute(ctx, (message.channel_id, message.id, Some(message.author.id)))
             .await?)
     }
+
+    async fn edit_interaction_message(self, ctx: &Context, message: &Message, token: &str) -> Result<Message> {
+        if message.flags.is_some_and(|flags| flags.contains(MessageFlags::EPHEMERAL)) {
+            Ok(self.to_slash_initial_response_edit(Default::default()).execute(ctx, token).await?)
+        } else {
+            self.edit_message(ctx, message).await
+        }
+    }
 }
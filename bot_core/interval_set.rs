@@ -15,10 +15,25 @@ impl<T: Ord + Copy> IntervalSet<T> {
         self.normalize();
     }
 
+    /// Remove the range from the set, splitting intervals where necessary
+    pub fn remove(&mut self, start: T, end: T) {
+        self.intervals = self
+            .intervals
+            .iter()
+            .flat_map(|r| [r.start..r.end.min(start), r.start.max(end)..r.end])
+            .filter(|r| r.start < r.end)
+            .collect();
+    }
+
     pub fn find(&self, time: T) -> Option<Range<T>> {
         self.intervals.iter().find(|r| r.contains(&time)).cloned()
     }
 
+    /// The start of the first interval that begins after `time`
+    pub fn next_start(&self, time: T) -> Option<T> {
+        self.intervals.iter().map(|r| r.start).find(|&start| start > time)
+    }
+
     fn normalize(&mut self) {
         if self.intervals.is_empty() {
             return;
@@ -4,6 +4,7 @@ pub mod audio;
 pub mod autocomplete;
 pub mod choice_parameters;
 pub mod color_parameter;
+pub mod component_store;
 pub mod roles;
 pub mod ext {
     pub mod create_reply;
@@ -17,10 +18,10 @@ pub mod lock_set;
 pub mod serde;
 pub mod template;
 pub mod time;
+pub mod tts;
 pub mod voice_change;
 
 use crate::ext::option::OptionExt as _;
-use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone as _, Utc};
 use eyre::Result;
 use poise::serenity_prelude::{
     Builder as _, Cache, Context, CreateInteractionResponse, GuildId, Member, ModalInteraction, UserId,
@@ -86,9 +87,3 @@ pub fn get_member(ctx: &impl AsRef<Cache>, guild_id: GuildId, user_id: UserId) -
     let guild = ctx.as_ref().guild(guild_id).inspect_none(|| tracing::warn!("Guild not in cache: {guild_id}"))?;
     guild.members.get(&user_id).inspect_none(|| tracing::warn!("Member not found in cache: {guild_id}")).cloned()
 }
-
-pub fn naive_time_to_next_datetime(naive_time: NaiveTime) -> Option<DateTime<Local>> {
-    let now = Utc::now().naive_local();
-    let date = if naive_time > now.time() { now.date() } else { now.date().succ_opt().unwrap() };
-    Local.from_local_datetime(&NaiveDateTime::new(date, naive_time)).single()
-}
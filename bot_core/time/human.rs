@@ -0,0 +1,198 @@
+//! Parsing of the date and time expressions people type, e.g. "tonight 23:30", "fri 1am", "in 2h" or "tomorrow"
+
+use crate::time::{next_datetime_at, resolve_local};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeDelta, TimeZone, Timelike, Utc, Weekday};
+
+/// Words that can be used as a day
+pub const DAY_WORDS: [&str; 10] =
+    ["today", "tonight", "tomorrow", "monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+
+enum Day {
+    Date(NaiveDate),
+    /// Today for times in the evening, tomorrow for times after midnight
+    Tonight,
+    /// The next day with this weekday, possibly today
+    Weekday(Weekday),
+}
+
+/// Parses a time of day like `23:30`, `23`, `1am`, `11:30 pm`, `noon` or `midnight`
+pub fn parse_time(input: &str) -> Option<NaiveTime> {
+    let input = input.trim().to_lowercase();
+    match input.as_str() {
+        "noon" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return NaiveTime::from_hms_opt(0, 0, 0),
+        _ => {}
+    }
+
+    let (input, offset) = match (input.strip_suffix("am"), input.strip_suffix("pm")) {
+        (Some(time), _) => (time.trim(), Some(0)),
+        (_, Some(time)) => (time.trim(), Some(12)),
+        _ => (input.as_str(), None),
+    };
+    let (hour, minute) = input.split_once(':').unwrap_or((input, "0"));
+    let (mut hour, minute): (u32, u32) = (hour.parse().ok()?, minute.parse().ok()?);
+    if let Some(offset) = offset {
+        if !(1..=12).contains(&hour) {
+            return None;
+        }
+        hour = hour % 12 + offset;
+    }
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+/// Parses a duration like `2h`, `1h30m`, `90min` or `2 hours`
+pub fn parse_duration(input: &str) -> Option<TimeDelta> {
+    let input = input.to_lowercase().replace(' ', "");
+    let mut duration = TimeDelta::zero();
+    let mut rest = input.as_str();
+    while !rest.is_empty() {
+        let number_end = rest.find(|c: char| !c.is_ascii_digit())?;
+        let unit_end = rest[number_end..].find(|c: char| c.is_ascii_digit()).map_or(rest.len(), |i| number_end + i);
+        let number: i64 = rest[..number_end].parse().ok()?;
+        duration += match &rest[number_end..unit_end] {
+            "h" | "hr" | "hrs" | "hour" | "hours" => TimeDelta::hours(number),
+            "m" | "min" | "mins" | "minute" | "minutes" => TimeDelta::minutes(number),
+            _ => return None,
+        };
+        rest = &rest[unit_end..];
+    }
+    (!input.is_empty()).then_some(duration)
+}
+
+fn parse_day(input: &str, today: NaiveDate) -> Option<Day> {
+    let input = input.trim().to_lowercase();
+    match input.as_str() {
+        "today" => return Some(Day::Date(today)),
+        "tonight" => return Some(Day::Tonight),
+        "tomorrow" | "tmrw" => return today.succ_opt().map(Day::Date),
+        _ => {}
+    }
+    if let Ok(weekday) = input.parse::<Weekday>() {
+        return Some(Day::Weekday(weekday));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(&input, "%Y-%m-%d") {
+        return Some(Day::Date(date));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(&input, "%d.%m.%Y") {
+        return Some(Day::Date(date));
+    }
+    // a date without a year is the next one
+    let (day, month) = input.strip_suffix('.').unwrap_or(&input).split_once('.')?;
+    let (day, month) = (day.parse().ok()?, month.parse().ok()?);
+    let date = NaiveDate::from_ymd_opt(today.year(), month, day)?;
+    Some(Day::Date(if date < today { NaiveDate::from_ymd_opt(today.year() + 1, month, day)? } else { date }))
+}
+
+/// Parses a date like `tomorrow`, `fri`, `2025-01-31`, `31.01.2025` or `31.01.`, relative to `today`
+pub fn parse_date(input: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match parse_day(input, today)? {
+        Day::Date(date) => Some(date),
+        Day::Tonight => Some(today),
+        Day::Weekday(weekday) => Some(next_weekday(today, weekday)),
+    }
+}
+
+fn next_weekday(from: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days = (weekday.num_days_from_monday() + 7 - from.weekday().num_days_from_monday()) % 7;
+    from + TimeDelta::days(days.into())
+}
+
+/// Parses a point in time like `now`, `in 2h`, `23:30`, `tonight 1am`, `fri 23:00` or `2025-01-31 22:00`.
+/// Times without a day are the next time the wall clock in `tz` shows them.
+pub fn parse_datetime<Tz: TimeZone>(input: &str, tz: &Tz, now: DateTime<Utc>) -> Option<DateTime<Tz>> {
+    let input = input.trim().to_lowercase();
+    if input == "now" {
+        return Some(now.with_timezone(tz));
+    }
+    if let Some(duration) = input.strip_prefix("in ") {
+        return Some((now + parse_duration(duration)?).with_timezone(tz));
+    }
+
+    let local_now = now.with_timezone(tz);
+    let today = local_now.date_naive();
+    let words = input.split_whitespace().collect::<Vec<_>>();
+    let (day, time) = (0..words.len()).find_map(|split| {
+        let (first, second) = (words[..split].join(" "), words[split..].join(" "));
+        if split == 0 {
+            return parse_time(&second).map(|time| (None, time));
+        }
+        // the day can come before or after the time
+        parse_day(&first, today)
+            .zip(parse_time(&second))
+            .or_else(|| parse_time(&first).zip(parse_day(&second, today)).map(|(time, day)| (day, time)))
+            .map(|(day, time)| (Some(day), time))
+    })?;
+
+    let date = match day {
+        None => return next_datetime_at(tz, time, now),
+        Some(Day::Date(date)) => date,
+        // after midnight, tonight's early hours are already today
+        Some(Day::Tonight) if time.hour() < 12 && local_now.hour() >= 12 => today.succ_opt()?,
+        Some(Day::Tonight) => today,
+        Some(Day::Weekday(weekday)) => {
+            let date = next_weekday(today, weekday);
+            match resolve_local(tz, date.and_time(time)) {
+                Some(dt) if dt.to_utc() <= now => date + TimeDelta::weeks(1),
+                _ => date,
+            }
+        }
+    };
+    resolve_local(tz, date.and_time(time))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(date: NaiveDate, h: u32, m: u32) -> DateTime<Utc> {
+        date.and_hms_opt(h, m, 0).unwrap().and_utc()
+    }
+
+    #[test]
+    fn times() {
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0);
+        assert_eq!(parse_time("23:30"), time(23, 30));
+        assert_eq!(parse_time("7"), time(7, 0));
+        assert_eq!(parse_time("1am"), time(1, 0));
+        assert_eq!(parse_time("12am"), time(0, 0));
+        assert_eq!(parse_time("11:30 PM"), time(23, 30));
+        assert_eq!(parse_time("noon"), time(12, 0));
+        assert_eq!(parse_time("13pm"), None);
+        assert_eq!(parse_time("soon"), None);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("2h"), Some(TimeDelta::hours(2)));
+        assert_eq!(parse_duration("1h 30m"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_duration("45 minutes"), Some(TimeDelta::minutes(45)));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("2 days"), None);
+    }
+
+    #[test]
+    fn datetimes() {
+        // a wednesday
+        let today = NaiveDate::from_ymd_opt(2025, 1, 8).unwrap();
+        let now = at(today, 20, 0);
+        let parse = |input| parse_datetime(input, &Utc, now);
+        let tomorrow = today.succ_opt().unwrap();
+
+        assert_eq!(parse("now"), Some(now));
+        assert_eq!(parse("in 2h"), Some(at(today, 22, 0)));
+        assert_eq!(parse("23:30"), Some(at(today, 23, 30)));
+        assert_eq!(parse("7am"), Some(at(tomorrow, 7, 0)));
+        assert_eq!(parse("tonight 23:30"), Some(at(today, 23, 30)));
+        assert_eq!(parse("tonight 1am"), Some(at(tomorrow, 1, 0)));
+        assert_eq!(parse("fri 1am"), Some(at(NaiveDate::from_ymd_opt(2025, 1, 10).unwrap(), 1, 0)));
+        assert_eq!(parse("wed 19:00"), Some(at(NaiveDate::from_ymd_opt(2025, 1, 15).unwrap(), 19, 0)));
+        assert_eq!(parse("11 pm tomorrow"), Some(at(tomorrow, 23, 0)));
+        assert_eq!(parse("2025-02-01 22:00"), Some(at(NaiveDate::from_ymd_opt(2025, 2, 1).unwrap(), 22, 0)));
+        assert_eq!(parse("tomorrow"), None);
+        assert_eq!(parse_date("tomorrow", today), Some(tomorrow));
+        assert_eq!(parse_date("05.01.", today), NaiveDate::from_ymd_opt(2026, 1, 5));
+
+        let after_midnight = at(tomorrow, 0, 30);
+        assert_eq!(parse_datetime("tonight 1am", &Utc, after_midnight), Some(at(tomorrow, 1, 0)));
+    }
+}
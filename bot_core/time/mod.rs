@@ -1,8 +1,30 @@
 use chrono::prelude::{DateTime, TimeZone};
+use chrono::{NaiveDateTime, NaiveTime, TimeDelta, Utc};
 
+pub mod human;
 pub mod iso_week;
 pub mod iso_weekday;
 
 pub fn discord_timestamp<Tz: TimeZone>(time: DateTime<Tz>) -> String {
     format!("<t:{}:R>", time.timestamp())
 }
+
+/// The timezone of the machine the bot runs on, UTC if it can't be determined
+pub fn local_timezone() -> chrono_tz::Tz {
+    iana_time_zone::get_timezone().ok().and_then(|name| name.parse().ok()).unwrap_or(chrono_tz::Tz::UTC)
+}
+
+/// Resolve a wall clock time in `tz`.
+/// Ambiguous times (DST ending) resolve to the earlier one, skipped times (DST starting) are moved past the gap.
+pub fn resolve_local<Tz: TimeZone>(tz: &Tz, naive: NaiveDateTime) -> Option<DateTime<Tz>> {
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(naive + TimeDelta::hours(1))).earliest())
+}
+
+/// The next time after `now` at which the wall clock in `tz` shows `time`
+pub fn next_datetime_at<Tz: TimeZone>(tz: &Tz, time: NaiveTime, now: DateTime<Utc>) -> Option<DateTime<Tz>> {
+    let now = now.with_timezone(tz).naive_local();
+    let date = if time > now.time() { now.date() } else { now.date().succ_opt()? };
+    resolve_local(tz, date.and_time(time))
+}
@@ -1,5 +1,5 @@
-use bot_core::audio::Playable;
-use bot_core::hash_store;
+use crate::audio::Playable;
+use crate::hash_store;
 use eyre::{Result, ensure};
 use futures::{StreamExt, TryStreamExt, stream};
 
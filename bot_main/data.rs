@@ -16,6 +16,8 @@ pub struct GuildData(
     Arc<bot_cmd_ephemeral_voice_channels::StateT>,
     Arc<bot_cmd_periodic_region_change::StateT>,
     Arc<bot_cmd_economy::StateT>,
+    Arc<bot_cmd_role_buttons::StateT>,
+    Arc<bot_cmd_bedtime::StateT>,
 );
 
 impl GuildData {
@@ -29,6 +31,8 @@ impl GuildData {
             Default::default(),
             Default::default(),
             Default::default(),
+            Default::default(),
+            Default::default(),
         )
     }
 }
@@ -48,11 +48,25 @@ async fn main() -> Result<()> {
             bot_cmd_ask::ask(),
             bot_cmd_ask::configure_ask_game(),
             bot_cmd_ask::delete_ask_game(),
+            bot_cmd_ask::import_ask_games(),
+            bot_cmd_ask::games(),
             bot_cmd_bedtime::bedtime(),
             bot_cmd_bedtime::bedtimes(),
             bot_cmd_economy::account(),
+            bot_cmd_economy::blackjack(),
+            bot_cmd_economy::buy(),
+            bot_cmd_economy::coinflip(),
+            bot_cmd_economy::dice(),
+            bot_cmd_economy::fairness(),
             bot_cmd_economy::gamble(),
             bot_cmd_economy::leaderboard(),
+            bot_cmd_economy::loan(),
+            bot_cmd_economy::market(),
+            bot_cmd_economy::money_supply(),
+            bot_cmd_economy::pay(),
+            bot_cmd_economy::roulette(),
+            bot_cmd_economy::shop(),
+            bot_cmd_economy::table(),
             bot_cmd_eval::math(),
             bot_cmd_eval::typst(),
             bot_cmd_message::button(),
@@ -79,16 +93,20 @@ async fn main() -> Result<()> {
                         bot_cmd_ephemeral_voice_channels::on_voice_update(framework, guild_id, (old, new)).await?;
                         bot_cmd_periodic_region_change::on_voice_update(framework, guild_id, (old, new)).await?;
                         bot_cmd_activity_roles::on_voice_update(framework, guild_id, (old, new)).await?;
+                        bot_cmd_economy::on_voice_update(framework, guild_id, (old, new)).await?;
+                        bot_cmd_bedtime::on_voice_update(framework, guild_id, (old, new)).await?;
                     }
                     FullEvent::ChannelUpdate { old, new } => {
                         bot_cmd_ephemeral_voice_channels::on_channel_update(framework, old, new).await?;
                     }
                     FullEvent::PresenceUpdate { new_data } => {
                         bot_cmd_tts::on_presence_update(framework, new_data).await?;
+                        bot_cmd_bedtime::on_presence_update(framework, new_data).await?;
                     }
                     FullEvent::Message { new_message } => {
                         bot_cmd_role_icon::on_message(framework, new_message).await?;
                         bot_cmd_activity_roles::on_message(framework, new_message).await?;
+                        bot_cmd_economy::on_message(framework, new_message).await?;
                     }
                     FullEvent::InteractionCreate { interaction: Interaction::Component(component) } => {
                         let full_id = &component.data.custom_id;
@@ -118,9 +136,18 @@ async fn main() -> Result<()> {
                             bot_cmd_ask::SHOW_GAME_ROLES_SELECT_ID => {
                                 bot_cmd_ask::btn_show_game_role_selection(framework, component, param).await?;
                             }
+                            bot_cmd_ask::GAME_ROLES_PAGE_BUTTON_ID => {
+                                bot_cmd_ask::btn_game_roles_page(framework, component, param).await?;
+                            }
                             bot_cmd_ask::SUBMIT_GAME_ROLES_SELECT_ID => {
                                 bot_cmd_ask::select_roles(framework, component, param).await?;
                             }
+                            bot_cmd_ask::SHUFFLE_TEAMS_BUTTON_ID => {
+                                bot_cmd_ask::btn_shuffle_teams(framework, component).await?;
+                            }
+                            bot_cmd_ask::SHUFFLE_TEAMS_SUBMIT_BUTTON_ID => {
+                                bot_cmd_ask::btn_shuffle_teams_submit(framework, component, param).await?;
+                            }
                             bot_cmd_ask::LEAVE_SERVER_BUTTON_ID => {
                                 bot_cmd_ask::btn_leave_server(framework, component).await?;
                             }
@@ -133,6 +160,15 @@ async fn main() -> Result<()> {
                             bot_cmd_bedtime::SELECT_BEDTIME_ID => {
                                 bot_cmd_bedtime::btn_select_bedtime(framework, component).await?;
                             }
+                            bot_cmd_bedtime::SNOOZE_BUTTON_ID => {
+                                bot_cmd_bedtime::btn_snooze(framework, component, param).await?;
+                            }
+                            bot_cmd_bedtime::END_BUTTON_ID => {
+                                bot_cmd_bedtime::btn_edit_end(framework, component, param).await?;
+                            }
+                            bot_cmd_bedtime::RESTRICTION_BUTTON_ID => {
+                                bot_cmd_bedtime::btn_toggle_restriction(framework, component, param).await?;
+                            }
                             bot_cmd_role_buttons::SHOW_ID => {
                                 bot_cmd_role_buttons::btn_show_role_selection(framework, component, param).await?;
                             }
@@ -154,6 +190,12 @@ async fn main() -> Result<()> {
                             bot_cmd_economy::PAY_PLAYER_BUTTON_ID => {
                                 bot_cmd_economy::btn_pay_player(framework, component, param).await?;
                             }
+                            bot_cmd_economy::MARKET_STAKE_SELECT_ID => {
+                                bot_cmd_economy::btn_market_stake(framework, component, param).await?;
+                            }
+                            bot_cmd_economy::MARKET_RESOLVE_BUTTON_ID => {
+                                bot_cmd_economy::btn_market_resolve(framework, component, param).await?;
+                            }
                             unknown_id => {
                                 // convention: local interaction ids start with ~
                                 if !unknown_id.starts_with('~') {
@@ -187,6 +229,7 @@ async fn main() -> Result<()> {
                 bot_cmd_bedtime::setup(ctx.clone(), data.clone()).await?;
                 bot_cmd_periodic_region_change::setup(ctx.clone(), data.clone()).await?;
                 bot_cmd_activity_roles::setup(ctx.clone(), data.clone()).await?;
+                bot_cmd_economy::setup(ctx.clone(), data.clone()).await?;
 
                 Ok(data)
             })